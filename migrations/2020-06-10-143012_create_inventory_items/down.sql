@@ -0,0 +1 @@
+drop table inventory_items
@@ -0,0 +1,9 @@
+create table inventory_items (
+	id bigint not null primary key,
+	character_id integer not null references characters(id),
+	lot integer not null,
+	count integer not null,
+	slot integer not null,
+	inventory_type integer not null,
+	is_equipped boolean not null
+)
@@ -0,0 +1,19 @@
+create table characters_backup as select id, username, name, torso_color, legs_color, hair_style, hair_color, eyebrows_style, eyes_style, mouth_style, world_zone, world_instance, world_clone from characters;
+drop table characters;
+create table characters (
+	id integer not null primary key,
+	username text not null,
+	name text unique not null,
+	torso_color integer not null,
+	legs_color integer not null,
+	hair_style integer not null,
+	hair_color integer not null,
+	eyebrows_style integer not null,
+	eyes_style integer not null,
+	mouth_style integer not null,
+	world_zone integer not null,
+	world_instance integer not null,
+	world_clone integer not null
+);
+insert into characters select * from characters_backup;
+drop table characters_backup;
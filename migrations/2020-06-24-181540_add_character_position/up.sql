@@ -0,0 +1,7 @@
+alter table characters add column pos_x float;
+alter table characters add column pos_y float;
+alter table characters add column pos_z float;
+alter table characters add column rot_x float;
+alter table characters add column rot_y float;
+alter table characters add column rot_z float;
+alter table characters add column rot_w float;
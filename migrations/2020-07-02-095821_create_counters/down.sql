@@ -0,0 +1,2 @@
+update inventory_items set id = id | (1 << 60);
+drop table counters;
@@ -0,0 +1,10 @@
+create table counters (
+	name text not null primary key,
+	value bigint not null
+);
+-- IDs are stored without the persistent flag, which is only applied when talking to the client
+update inventory_items set id = id & ~(1 << 60);
+insert into counters (name, value) values ('object_id', max(
+	coalesce((select max(id) from characters), 0),
+	coalesce((select max(id) from inventory_items), 0)
+));
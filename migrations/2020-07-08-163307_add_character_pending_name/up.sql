@@ -0,0 +1 @@
+alter table characters add column pending_name text;
@@ -0,0 +1,27 @@
+create table characters_backup as select id, username, name, torso_color, legs_color, hair_style, hair_color, eyebrows_style, eyes_style, mouth_style, world_zone, world_instance, world_clone, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w, pending_name from characters;
+drop table characters;
+create table characters (
+	id integer not null primary key,
+	username text not null,
+	name text unique not null,
+	torso_color integer not null,
+	legs_color integer not null,
+	hair_style integer not null,
+	hair_color integer not null,
+	eyebrows_style integer not null,
+	eyes_style integer not null,
+	mouth_style integer not null,
+	world_zone integer not null,
+	world_instance integer not null,
+	world_clone integer not null,
+	pos_x float,
+	pos_y float,
+	pos_z float,
+	rot_x float,
+	rot_y float,
+	rot_z float,
+	rot_w float,
+	pending_name text
+);
+insert into characters select * from characters_backup;
+drop table characters_backup;
@@ -0,0 +1,2 @@
+alter table characters add column name_approved boolean not null default 1;
+update characters set name_approved = 0 where pending_name is not null;
@@ -0,0 +1 @@
+alter table characters add column last_login bigint not null default 0;
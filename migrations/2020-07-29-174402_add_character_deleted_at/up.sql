@@ -0,0 +1 @@
+alter table characters add column deleted_at bigint;
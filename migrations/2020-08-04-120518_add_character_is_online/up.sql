@@ -0,0 +1 @@
+alter table characters add column is_online boolean not null default 0;
@@ -1,4 +1,7 @@
-use std::io::Result as Res;
+use std::io::{Error, ErrorKind::Other, Result as Res};
+
+use diesel::prelude::*;
+use diesel::dsl::update;
 
 use lu_packets::{
 	amf3, lnv, lu,
@@ -8,22 +11,37 @@ use lu_packets::{
 };
 
 use crate::game_object::GameObject;
-use crate::state::{Connection, State};
-use crate::services::{AddItem, GetPosition, GetRotation, SetFaction};
+use crate::listeners::{restore_cutoff, MAX_CHARACTERS};
+use crate::models::Character;
+use crate::state::{AccountInfo, Connection, State};
+use crate::services::{AddItem, ChangeImagination, Damage, GetPosition, GetRotation, Heal, IsDead, RepairArmor, SetFaction};
+
+/// Commands only moderators can use, they are unknown to everyone else.
+const MODERATOR_COMMANDS: &[&str] = &["approvename", "pendingnames", "rejectname", "restorechar"];
 
-pub fn on_chat_command(state: &mut State, string: &str, sender: &mut GameObject, conn: &mut Connection) {
+pub fn on_chat_command(state: &mut State, string: &str, acc_info: &AccountInfo, sender: &mut GameObject, conn: &mut Connection) {
 	let args: Vec<_> = string.split_whitespace().collect();
-	let command = match &args[0][1..] {
-		"additem"   => add_item_cmd,
-		"faction"   => faction_cmd,
-		"gamestate" => send_gamestate_cmd,
-		"jetpack"   => jetpack_cmd,
-		"uidebug"   => send_uidebug_cmd,
-		"toggle"    => send_toggle_scoreboard_cmd,
-		"spawn"     => spawn_cmd,
-		"dance"     => nop_cmd,
-		_           => unknown_cmd,
+	let name = &args[0][1..];
+	let command = match name {
+		"additem"      => add_item_cmd,
+		"approvename"  => approve_name_cmd,
+		"damage"       => damage_cmd,
+		"faction"      => faction_cmd,
+		"gamestate"    => send_gamestate_cmd,
+		"heal"         => heal_cmd,
+		"imagination"  => imagination_cmd,
+		"jetpack"      => jetpack_cmd,
+		"pendingnames" => pending_names_cmd,
+		"rejectname"   => reject_name_cmd,
+		"repairarmor"  => repair_armor_cmd,
+		"restorechar"  => restore_char_cmd,
+		"uidebug"      => send_uidebug_cmd,
+		"toggle"       => send_toggle_scoreboard_cmd,
+		"spawn"        => spawn_cmd,
+		"dance"        => nop_cmd,
+		_              => unknown_cmd,
 	};
+	let command = if MODERATOR_COMMANDS.contains(&name) && !state.is_moderator(acc_info.username()) { unknown_cmd } else { command };
 
 	if let Err(error) = command(state, sender, conn, &args) {
 		conn.send(system_message(&format!("Error in command: {}", error))).unwrap();
@@ -42,12 +60,106 @@ fn system_message(string: &str) -> ClientChatMessage {
 }
 
 fn add_item_cmd(state: &mut State, sender: &mut GameObject, conn: &mut Connection, args: &Vec<&str>) -> Res<()> {
+	if args.len() != 2 && args.len() != 3 {
+		return Ok(());
+	}
+	let lot = match args[1].parse() {
+		Ok(x) => x,
+		Err(_) => return conn.send(system_message("Usage: /additem <lot> [count]")),
+	};
+	let count = match args.get(2).map(|x| x.parse()) {
+		None => 1,
+		Some(Ok(x)) => x,
+		Some(Err(_)) => return conn.send(system_message("Usage: /additem <lot> [count]")),
+	};
+	let mut add_item = AddItem { lot, count, added: 0 };
+	sender.run_service_mut(&mut add_item, state, conn)?;
+	if add_item.added < count {
+		conn.send(system_message("Inventory full."))?;
+	}
+	Ok(())
+}
+
+fn pending_names_cmd(state: &mut State, _sender: &mut GameObject, conn: &mut Connection, _args: &Vec<&str>) -> Res<()> {
+	use crate::schema::characters::dsl::{characters, pending_name};
+
+	let pending: Vec<Character> = match characters.filter(pending_name.is_not_null()).load(state.db()) {
+		Ok(x) => x,
+		Err(e) => return Err(Error::new(Other, format!("Error loading pending names: {}", e))),
+	};
+	if pending.is_empty() {
+		return conn.send(system_message("No names pending."));
+	}
+	for chara in pending {
+		conn.send(system_message(&format!("{} wants to be {}", chara.name, chara.pending_name.unwrap())))?;
+	}
+	Ok(())
+}
+
+fn approve_name_cmd(state: &mut State, _sender: &mut GameObject, conn: &mut Connection, args: &Vec<&str>) -> Res<()> {
+	use crate::schema::characters::dsl::{characters, name, pending_name, name_approved};
+
 	if args.len() != 2 {
 		return Ok(());
 	}
-	let lot = args[1].parse().unwrap();
-	let add_item = AddItem { lot };
-	sender.run_service_mut(&add_item, state, conn)
+	let chara: Option<Character> = characters.filter(name.eq(args[1])).filter(pending_name.is_not_null()).first(state.db()).optional().unwrap_or(None);
+	let chara = match chara {
+		Some(x) => x,
+		None => return conn.send(system_message(&format!("{} has no pending name.", args[1]))),
+	};
+	let new_name = chara.pending_name.unwrap();
+	if let Err(e) = update(characters.find(chara.id))
+	.set((name.eq(&new_name), pending_name.eq(None::<String>), name_approved.eq(true)))
+	.execute(state.db()) {
+		return Err(Error::new(Other, format!("Error approving name: {}", e)));
+	}
+	conn.send(system_message(&format!("{} is now {}.", chara.name, new_name)))
+}
+
+fn reject_name_cmd(state: &mut State, _sender: &mut GameObject, conn: &mut Connection, args: &Vec<&str>) -> Res<()> {
+	use crate::schema::characters::dsl::{characters, name, pending_name, name_approved};
+
+	if args.len() != 2 {
+		return Ok(());
+	}
+	let updated = update(characters.filter(name.eq(args[1])).filter(pending_name.is_not_null()))
+	.set((pending_name.eq(None::<String>), name_approved.eq(false)))
+	.execute(state.db());
+	match updated {
+		Ok(0) => conn.send(system_message(&format!("{} has no pending name.", args[1]))),
+		Ok(_) => conn.send(system_message(&format!("Rejected the pending name of {}, they will have to rename.", args[1]))),
+		Err(e) => Err(Error::new(Other, format!("Error rejecting name: {}", e))),
+	}
+}
+
+fn restore_char_cmd(state: &mut State, _sender: &mut GameObject, conn: &mut Connection, args: &Vec<&str>) -> Res<()> {
+	use crate::schema::characters::dsl::{characters, name, username, deleted_at};
+
+	if args.len() != 2 {
+		return Ok(());
+	}
+	let chara: Option<Character> = characters
+	.filter(name.eq(args[1]))
+	.filter(deleted_at.ge(restore_cutoff(state)))
+	.first(state.db()).optional().unwrap_or(None);
+	let chara = match chara {
+		Some(x) => x,
+		None => return conn.send(system_message(&format!("{} is not a restorable character.", args[1]))),
+	};
+	let char_count: i64 = characters
+	.filter(username.eq(&chara.username))
+	.filter(deleted_at.is_null())
+	.count()
+	.get_result(state.db()).unwrap_or(MAX_CHARACTERS);
+	if char_count >= MAX_CHARACTERS {
+		return conn.send(system_message(&format!("{} already has {} characters.", chara.username, MAX_CHARACTERS)));
+	}
+	if let Err(e) = update(characters.find(chara.id))
+	.set(deleted_at.eq(None::<i64>))
+	.execute(state.db()) {
+		return Err(Error::new(Other, format!("Error restoring character: {}", e)));
+	}
+	conn.send(system_message(&format!("Restored {}.", chara.name)))
 }
 
 fn faction_cmd(state: &mut State, sender: &mut GameObject, conn: &mut Connection, args: &Vec<&str>) -> Res<()> {
@@ -59,6 +171,69 @@ fn faction_cmd(state: &mut State, sender: &mut GameObject, conn: &mut Connection
 	sender.run_service_mut(&set_faction, state, conn)
 }
 
+/// Damages the sender, or the object with the given ID, which can't be another player.
+fn damage_cmd(state: &mut State, sender: &mut GameObject, conn: &mut Connection, args: &Vec<&str>) -> Res<()> {
+	let amount = match args.get(1).map(|x| x.parse()) {
+		Some(Ok(x)) => x,
+		_ => return conn.send(system_message("Usage: /damage <amount> [object id]")),
+	};
+	let target = match args.get(2).map(|x| x.parse()) {
+		None => sender.object_id(),
+		Some(Ok(x)) => x,
+		Some(Err(_)) => return conn.send(system_message("Usage: /damage <amount> [object id]")),
+	};
+	let mut damage = Damage { amount, source: sender.object_id(), dealt: 0, killed: false };
+	let mut is_dead = IsDead::default();
+	if target == sender.object_id() {
+		sender.run_service(&mut is_dead);
+		if is_dead.0 {
+			return conn.send(system_message("You are already dead."));
+		}
+		sender.run_service_mut(&mut damage, state, conn)?;
+		return conn.send(system_message(&format!("Took {} damage.", damage.dealt)));
+	}
+
+	if state.is_player(target) {
+		return conn.send(system_message("You can't damage other players."));
+	}
+	match state.game_object(target) {
+		Some(x) => x.run_service(&mut is_dead),
+		None => return conn.send(system_message(&format!("Object {} does not exist.", target))),
+	}
+	if is_dead.0 {
+		return conn.send(system_message(&format!("Object {} is already dead.", target)));
+	}
+	state.with_game_object(target, |state, game_object| game_object.run_service_mut(&mut damage, state, conn))?;
+	if damage.killed {
+		return conn.send(system_message(&format!("Dealt {} damage to object {}, killing it.", damage.dealt, target)));
+	}
+	conn.send(system_message(&format!("Dealt {} damage to object {}.", damage.dealt, target)))
+}
+
+fn heal_cmd(state: &mut State, sender: &mut GameObject, conn: &mut Connection, args: &Vec<&str>) -> Res<()> {
+	let amount = match args.get(1).map(|x| x.parse()) {
+		Some(Ok(x)) => x,
+		_ => return conn.send(system_message("Usage: /heal <amount>")),
+	};
+	sender.run_service_mut(&Heal(amount), state, conn)
+}
+
+fn repair_armor_cmd(state: &mut State, sender: &mut GameObject, conn: &mut Connection, args: &Vec<&str>) -> Res<()> {
+	let amount = match args.get(1).map(|x| x.parse()) {
+		Some(Ok(x)) => x,
+		_ => return conn.send(system_message("Usage: /repairarmor <amount>")),
+	};
+	sender.run_service_mut(&RepairArmor(amount), state, conn)
+}
+
+fn imagination_cmd(state: &mut State, sender: &mut GameObject, conn: &mut Connection, args: &Vec<&str>) -> Res<()> {
+	let change = match args.get(1).map(|x| x.parse()) {
+		Some(Ok(x)) => x,
+		_ => return conn.send(system_message("Usage: /imagination <change>")),
+	};
+	sender.run_service_mut(&ChangeImagination(change), state, conn)
+}
+
 fn jetpack_cmd(_state: &mut State, sender: &mut GameObject, conn: &mut Connection, _args: &Vec<&str>) -> Res<()> {
 	let uimsg = sender.make_sgm(SetJetPackMode {
 		bypass_checks: true,
@@ -134,10 +309,8 @@ fn spawn_cmd(state: &mut State, sender: &mut GameObject, conn: &mut Connection,
 		"rotation_z": get_rot.0.z,
 		"rotation_w": get_rot.0.w,
 	};
-	let game_object = state.spawn(lot, &config)?;
-
-	let replica = game_object.make_construction();
-	conn.broadcast(replica)
+	let obj_id = state.spawn(lot, &config)?.object_id();
+	state.construct(obj_id, conn)
 }
 
 fn nop_cmd(_state: &mut State, _sender: &mut GameObject, _conn: &mut Connection, _args: &Vec<&str>) -> Res<()> {
@@ -1,29 +1,63 @@
-use std::io::Result as Res;
+use std::io::{Error, ErrorKind::Other, Result as Res};
+
+use diesel::prelude::*;
+use rusqlite::{Connection as RusqliteConnection, params};
 
 use lu_packets::{
 	lu,
 	raknet::client::replica::character::{CharacterConstruction, CharacterProtocol, CharacterSerialization, GameActivity, GmPvpInfo, SocialInfo, TransitionState},
-	world::LuNameValue,
+	world::{Lot, LuNameValue, LnvValue},
 	world::gm::client::EmotePlayed,
-	world::gm::server::{GameMessage as ServerGM, ParseChatMessage, PlayEmote},
+	world::gm::server::{GameMessage as ServerGM, PlayEmote, RequestUse},
 };
 
+use crate::models::Character;
 use crate::state::Connection;
 use crate::state::State;
 use super::{InternalComponent, GameObject};
 
-pub struct CharacterComponent {
+/// How far away from an object a player can use it, to allow for lag.
+const MAX_USE_DISTANCE: f32 = 20.0;
 
+pub struct CharacterComponent {
+	/// DB ID of the character, if this is a player.
+	char_id: Option<i32>,
+	hair_color: u32,
+	hair_style: u32,
+	torso_color: u32,
+	legs_color: u32,
+	eyebrows_style: u32,
+	eyes_style: u32,
+	mouth_style: u32,
 }
 
 impl CharacterComponent {
-	fn on_parse_chat_message(&mut self, msg: &ParseChatMessage, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
-		use lu_packets::common::LuStrExt;
-		let string = msg.string.to_string();
+	/// Looks up the zone a rocket launchpad sends players to, `None` if the LOT is not a launchpad.
+	fn launchpad_target_zone(lot: Lot, cdclient: &RusqliteConnection) -> Option<u16> {
+		let mut stmt = cdclient.prepare("select targetZone, defaultZoneID from rocketlaunchpadcontrolcomponent where id = (select component_id from componentsregistry where id = ? and component_type = 67)").unwrap();
+		let (target_zone, default_zone): (Option<u16>, Option<u16>) = stmt.query_row(params![lot], |row| Ok((row.get(0)?, row.get(1)?))).ok()?;
+		target_zone.filter(|x| *x != 0).or(default_zone)
+	}
 
-		if string.starts_with("/") {
-			dbg!(msg);
-			crate::commands::on_chat_command(state, &string, game_object, conn);
+	fn on_request_use(&mut self, msg: &RequestUse, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		let char_id = match self.char_id {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+		let target = match state.game_object(msg.object) {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+		if let (Some(pos), Some(target_pos)) = (game_object.position(), target.position()) {
+			let (dx, dy, dz) = (pos.x - target_pos.x, pos.y - target_pos.y, pos.z - target_pos.z);
+			if dx*dx + dy*dy + dz*dz > MAX_USE_DISTANCE*MAX_USE_DISTANCE {
+				println!("{} tried to use {} from out of range!", game_object.object_id(), msg.object);
+				return Ok(());
+			}
+		}
+		let lot = target.lot();
+		if let Some(zone_id) = Self::launchpad_target_zone(lot, state.cdclient()) {
+			crate::listeners::transfer_to_zone(state, char_id, zone_id, conn)?;
 		}
 		Ok(())
 	}
@@ -40,8 +74,19 @@ impl CharacterComponent {
 impl InternalComponent for CharacterComponent {
 	type ComponentProtocol = CharacterProtocol;
 
-	fn new(_config: &LuNameValue) -> Self {
-		Self {}
+	fn new(config: &LuNameValue) -> Self {
+		let char_id = if let Some(LnvValue::I32(x)) = config.get(&lu!("char_id")) { Some(*x) } else { None };
+
+		Self {
+			char_id,
+			hair_color: 0,
+			hair_style: 0,
+			torso_color: 0,
+			legs_color: 0,
+			eyebrows_style: 0,
+			eyes_style: 0,
+			mouth_style: 0,
+		}
 	}
 
 	fn make_construction(&self) -> CharacterConstruction {
@@ -50,15 +95,16 @@ impl InternalComponent for CharacterComponent {
 			claim_code_2: None,
 			claim_code_3: None,
 			claim_code_4: None,
-			hair_color: 9,
-			hair_style: 9,
-			torso_color: 9,
-			legs_color: 14,
+			hair_color: self.hair_color,
+			hair_style: self.hair_style,
+			torso_color: self.torso_color,
+			legs_color: self.legs_color,
 			torso_decal: 24,
-			eyebrows_style: 21,
-			eyes_style: 8,
-			mouth_style: 6,
-			account_id: 104116,
+			eyebrows_style: self.eyebrows_style,
+			eyes_style: self.eyes_style,
+			mouth_style: self.mouth_style,
+			// accounts are managed by the auth server, which doesn't share numeric IDs with us
+			account_id: 0,
 			last_logout: 0,
 			prop_mod_last_display_time: 0,
 			u_score: 0,
@@ -115,10 +161,46 @@ impl InternalComponent for CharacterComponent {
 		}
 	}
 
+	fn write_xml(&self, writer: &mut String) -> std::fmt::Result {
+		use std::fmt::Write;
+		if self.char_id.is_none() {
+			return Ok(());
+		}
+		write!(writer, "<mf hc=\"{}\" hs=\"{}\" hd=\"0\" t=\"{}\" l=\"{}\" hdc=\"0\" cd=\"24\" lh=\"0\" rh=\"0\" es=\"{}\" ess=\"{}\" ms=\"{}\"/>",
+			self.hair_color, self.hair_style, self.torso_color, self.legs_color, self.eyebrows_style, self.eyes_style, self.mouth_style)?;
+		write!(writer, "<char acct=\"0\" cc=\"0\" gm=\"0\" ft=\"0\"/>")?;
+		Ok(())
+	}
+
+	fn load(&mut self, _game_object: &GameObject, state: &State) -> Res<()> {
+		use crate::schema::characters::dsl::characters;
+
+		let char_id = match self.char_id {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+		let chara: Character = match characters.find(char_id).first(state.db()).optional() {
+			Ok(Some(x)) => x,
+			Ok(None) => {
+				eprintln!("Character {} does not exist!", char_id);
+				return Ok(());
+			}
+			Err(e) => return Err(Error::new(Other, format!("Error loading character {}: {}", char_id, e))),
+		};
+		self.hair_color = chara.hair_color as u32;
+		self.hair_style = chara.hair_style as u32;
+		self.torso_color = chara.torso_color as u32;
+		self.legs_color = chara.legs_color as u32;
+		self.eyebrows_style = chara.eyebrows_style as u32;
+		self.eyes_style = chara.eyes_style as u32;
+		self.mouth_style = chara.mouth_style as u32;
+		Ok(())
+	}
+
 	fn on_game_message(&mut self, msg: &ServerGM, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
 		match msg {
-			ServerGM::ParseChatMessage(x) => self.on_parse_chat_message(x, game_object, state, conn),
 			ServerGM::PlayEmote(x) => self.on_play_emote(x, game_object, state, conn),
+			ServerGM::RequestUse(x) => self.on_request_use(x, game_object, state, conn),
 			_ => Ok(()),
 		}
 	}
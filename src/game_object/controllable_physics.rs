@@ -17,6 +17,7 @@ pub struct ControllablePhysicsComponent {
 	is_on_rail: bool,
 	linear_velocity: Option<Vector3>,
 	angular_velocity: Option<Vector3>,
+	frame_stats_dirty: bool,
 }
 
 impl ControllablePhysicsComponent {
@@ -27,6 +28,7 @@ impl ControllablePhysicsComponent {
 		self.is_on_rail = frame_stats.is_on_rail;
 		self.linear_velocity = frame_stats.linear_velocity;
 		self.angular_velocity = frame_stats.angular_velocity;
+		self.frame_stats_dirty = true;
 		Ok(())
 	}
 }
@@ -35,9 +37,9 @@ impl InternalComponent for ControllablePhysicsComponent {
 	type ComponentProtocol = ControllablePhysicsProtocol;
 
 	fn new(config: &LuNameValue) -> Self {
-		let pos_x = if let Some(LnvValue::F32(x)) = config.get(&lu!("position_x")) { *x } else { 156.0 };
-		let pos_y = if let Some(LnvValue::F32(x)) = config.get(&lu!("position_y")) { *x } else { 380.0 };
-		let pos_z = if let Some(LnvValue::F32(x)) = config.get(&lu!("position_z")) { *x } else { -187.0 };
+		let pos_x = if let Some(LnvValue::F32(x)) = config.get(&lu!("position_x")) { *x } else { 0.0 };
+		let pos_y = if let Some(LnvValue::F32(x)) = config.get(&lu!("position_y")) { *x } else { 0.0 };
+		let pos_z = if let Some(LnvValue::F32(x)) = config.get(&lu!("position_z")) { *x } else { 0.0 };
 
 		let rot_x = if let Some(LnvValue::F32(x)) = config.get(&lu!("rotation_x")) { *x } else { 0.0 };
 		let rot_y = if let Some(LnvValue::F32(x)) = config.get(&lu!("rotation_y")) { *x } else { 0.0 };
@@ -51,6 +53,7 @@ impl InternalComponent for ControllablePhysicsComponent {
 			is_on_rail: false,
 			linear_velocity: None,
 			angular_velocity: None,
+			frame_stats_dirty: false,
 		}
 	}
 
@@ -78,7 +81,7 @@ impl InternalComponent for ControllablePhysicsComponent {
 			cheat_info: None,
 			unknown_1: None,
 			unknown_2: None,
-			frame_stats_teleport_info: Some(FrameStatsTeleportInfo {
+			frame_stats_teleport_info: if !self.frame_stats_dirty { None } else { Some(FrameStatsTeleportInfo {
 				frame_stats: FrameStats {
 					position: self.position,
 					rotation: self.rotation,
@@ -89,10 +92,18 @@ impl InternalComponent for ControllablePhysicsComponent {
 					local_space_info: None,
 				},
 				is_teleporting: false,
-			}),
+			})},
 		}
 	}
 
+	fn is_dirty(&self) -> bool {
+		self.frame_stats_dirty
+	}
+
+	fn clear_dirty(&mut self) {
+		self.frame_stats_dirty = false;
+	}
+
 	fn run_service(&self, service: &mut GameObjectService, _game_object: &GameObject) {
 		match service {
 			GameObjectService::GetPosition(x) => {
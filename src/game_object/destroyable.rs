@@ -1,21 +1,145 @@
 use std::io::Result as Res;
 
+use rusqlite::params;
+
 use lu_packets::{
+	lu,
+	common::ObjId,
 	raknet::client::replica::destroyable::{DestroyableConstruction, DestroyableProtocol, DestroyableSerialization, SerializationStatsInfo, StatsInfo, StatusImmunityInfo},
-	world::LuNameValue,
+	world::{LuNameValue, Vector3},
+	world::gm::client::{Die, KillType, Resurrect},
+	world::gm::server::{GameMessage as ServerGM, RequestDie},
 };
 
 use super::{GameObject, InternalComponent};
-use crate::services::{GameObjectServiceMut, SetFaction};
+use crate::loot;
+use crate::services::{ChangeImagination, Damage, GameObjectService, GameObjectServiceMut, Heal, IsDead, RepairArmor, SetFaction};
 use crate::state::{Connection, State};
 
+/// Seconds from the death of a non-player object until it is destroyed, so clients can play the death or smash animation.
+const DESTROY_DELAY: f32 = 2.0;
+const DESTROY_TIMER: &str = "destroy";
+
 pub struct DestroyableComponent {
-	faction: i32,
+	/// Players stay in the world when they die, and can resurrect.
+	is_player: bool,
+	health: u32,
+	max_health: f32,
+	armor: u32,
+	max_armor: f32,
+	imagination: u32,
+	max_imagination: f32,
+	factions: Vec<i32>,
+	is_smashable: bool,
+	/// Index into the cdclient `LootMatrix` of what the object drops on death.
+	loot_matrix_index: Option<i32>,
+	is_dead: bool,
+	/// Whether the object died from damage and is smashable, clients play the smash effect for it.
+	is_smashed: bool,
+	stats_dirty: bool,
 }
 
 impl DestroyableComponent {
 	fn set_faction(&mut self, set_faction: &SetFaction) -> Res<()> {
-		self.faction = set_faction.0;
+		self.factions = vec![set_faction.0];
+		self.stats_dirty = true;
+		Ok(())
+	}
+
+	/// Takes damage, armor absorbs it before health.
+	fn damage(&mut self, damage: &mut Damage) -> Res<()> {
+		if self.is_dead {
+			return Ok(());
+		}
+		let absorbed = damage.amount.min(self.armor);
+		self.armor -= absorbed;
+		let taken = (damage.amount - absorbed).min(self.health);
+		self.health -= taken;
+		damage.dealt = absorbed + taken;
+		damage.killed = self.health == 0;
+		self.stats_dirty = true;
+		Ok(())
+	}
+
+	/// Puts the object in the dead state and tells the clients, non-player objects are destroyed after a delay.
+	fn die(&mut self, die: Die, game_object: &mut GameObject, conn: &mut Connection) -> Res<()> {
+		self.health = 0;
+		self.is_dead = true;
+		self.is_smashed = self.is_smashable;
+		self.stats_dirty = true;
+		conn.broadcast(game_object.make_sgm(die))?;
+		if !self.is_player {
+			game_object.add_timer(DESTROY_TIMER, DESTROY_DELAY);
+		}
+		Ok(())
+	}
+
+	/// Rolls the object's loot and drops it for the killer.
+	fn drop_loot(&self, owner: ObjId, game_object: &GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		let loot_matrix_index = match self.loot_matrix_index {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+		let position = game_object.position().unwrap_or(Vector3::ZERO);
+		for lot in loot::roll_loot(state.cdclient(), loot_matrix_index) {
+			state.drop_loot(lot, owner, game_object.object_id(), position, conn)?;
+		}
+		Ok(())
+	}
+
+	fn on_request_die(&mut self, msg: &RequestDie, game_object: &mut GameObject, conn: &mut Connection) -> Res<()> {
+		if self.is_dead {
+			return Ok(());
+		}
+		self.die(Die {
+			client_death: true,
+			spawn_loot: false,
+			death_type: msg.death_type.clone(),
+			direction_relative_angle_xz: msg.direction_relative_angle_xz,
+			direction_relative_angle_y: msg.direction_relative_angle_y,
+			direction_relative_force: msg.direction_relative_force,
+			kill_type: msg.kill_type,
+			killer_id: msg.killer_id,
+			loot_owner_id: msg.loot_owner_id,
+		}, game_object, conn)
+	}
+
+	fn on_request_resurrect(&mut self, game_object: &mut GameObject, conn: &mut Connection) -> Res<()> {
+		if !self.is_player || !self.is_dead {
+			return Ok(());
+		}
+		// resurrecting restores all stats, not just health
+		self.health = self.max_health as u32;
+		self.armor = self.max_armor as u32;
+		self.imagination = self.max_imagination as u32;
+		self.is_dead = false;
+		self.is_smashed = false;
+		self.stats_dirty = true;
+		conn.broadcast(game_object.make_sgm(Resurrect { resurrect_immediately: false }))
+	}
+
+	fn heal(&mut self, heal: &Heal) -> Res<()> {
+		if self.is_dead {
+			return Ok(());
+		}
+		self.health = self.health.saturating_add(heal.0).min(self.max_health as u32);
+		self.stats_dirty = true;
+		Ok(())
+	}
+
+	fn repair_armor(&mut self, repair: &RepairArmor) -> Res<()> {
+		if self.is_dead {
+			return Ok(());
+		}
+		self.armor = self.armor.saturating_add(repair.0).min(self.max_armor as u32);
+		self.stats_dirty = true;
+		Ok(())
+	}
+
+	fn change_imagination(&mut self, change: &ChangeImagination) -> Res<()> {
+		let imagination = (self.imagination as i64 + change.0 as i64).max(0).min(self.max_imagination as i64);
+		self.imagination = imagination as u32;
+		self.stats_dirty = true;
 		Ok(())
 	}
 }
@@ -23,9 +147,23 @@ impl DestroyableComponent {
 impl InternalComponent for DestroyableComponent {
 	type ComponentProtocol = DestroyableProtocol;
 
-	fn new(_config: &LuNameValue) -> Self {
+	fn new(config: &LuNameValue) -> Self {
+		let is_player = config.get(&lu!("char_id")).is_some();
+
 		Self {
-			faction: 1,
+			is_player,
+			health: 4,
+			max_health: 4.0,
+			armor: 0,
+			max_armor: 0.0,
+			imagination: 0,
+			max_imagination: 0.0,
+			factions: vec![1],
+			is_smashable: false,
+			loot_matrix_index: None,
+			is_dead: false,
+			is_smashed: false,
+			stats_dirty: false,
 		}
 	}
 
@@ -43,22 +181,22 @@ impl InternalComponent for DestroyableComponent {
 				immune_to_pull_to_point: 0,
 			}),
 			stats_info: Some(StatsInfo {
-				cur_health: 4,
-				max_health: 4.0,
-				cur_armor: 0,
-				max_armor: 0.0,
-				cur_imag: 0,
-				max_imag: 0.0,
+				cur_health: self.health,
+				max_health: self.max_health,
+				cur_armor: self.armor,
+				max_armor: self.max_armor,
+				cur_imag: self.imagination,
+				max_imag: self.max_imagination,
 				damage_absorption_points: 0,
-				immunity: true,
+				immunity: false,
 				is_gm_immune: false,
 				is_shielded: false,
-				actual_max_health: 4.0,
-				actual_max_armor: 0.0,
-				actual_max_imag: 0.0,
-				factions: vec![self.faction].into(),
-				is_dead: false,
-				is_smashed: false,
+				actual_max_health: self.max_health,
+				actual_max_armor: self.max_armor,
+				actual_max_imag: self.max_imagination,
+				factions: self.factions.clone().into(),
+				is_dead: self.is_dead,
+				is_smashed: self.is_smashed,
 				smashable_info: None,
 			}),
 			is_on_a_threat_list: Some(false),
@@ -67,31 +205,112 @@ impl InternalComponent for DestroyableComponent {
 
 	fn make_serialization(&self) -> DestroyableSerialization {
 		DestroyableSerialization {
-			serialization_stats_info: Some(SerializationStatsInfo {
-				cur_health: 4,
-				max_health: 4.0,
-				cur_armor: 0,
-				max_armor: 0.0,
-				cur_imag: 0,
-				max_imag: 0.0,
+			serialization_stats_info: if !self.stats_dirty { None } else { Some(SerializationStatsInfo {
+				cur_health: self.health,
+				max_health: self.max_health,
+				cur_armor: self.armor,
+				max_armor: self.max_armor,
+				cur_imag: self.imagination,
+				max_imag: self.max_imagination,
 				damage_absorption_points: 0,
-				immunity: true,
+				immunity: false,
 				is_gm_immune: false,
 				is_shielded: false,
-				actual_max_health: 4.0,
-				actual_max_armor: 0.0,
-				actual_max_imag: 0.0,
-				factions: vec![self.faction].into(),
-				is_smashable: false,
-			}),
+				actual_max_health: self.max_health,
+				actual_max_armor: self.max_armor,
+				actual_max_imag: self.max_imagination,
+				factions: self.factions.clone().into(),
+				is_smashable: self.is_smashable,
+			})},
 			is_on_a_threat_list: None,
 		}
 	}
 
+	fn is_dirty(&self) -> bool {
+		self.stats_dirty
+	}
+
+	fn clear_dirty(&mut self) {
+		self.stats_dirty = false;
+	}
+
+	/// Loads the base stats from the cdclient `DestroyableComponent` table.
+	fn load(&mut self, game_object: &GameObject, state: &State) -> Res<()> {
+		let mut stmt = state.cdclient().prepare("select life, armor, imagination, faction, factionList, isSmashable, LootMatrixIndex from destroyablecomponent where id = (select component_id from componentsregistry where id = ? and component_type = 7)").unwrap();
+		let row = stmt.query_row(params![game_object.lot()], |row| {
+			Ok((row.get::<_, Option<f64>>(0)?, row.get::<_, Option<f64>>(1)?, row.get::<_, Option<f64>>(2)?, row.get::<_, Option<i32>>(3)?, row.get::<_, Option<String>>(4)?, row.get::<_, Option<bool>>(5)?, row.get::<_, Option<i32>>(6)?))
+		});
+		let (life, armor, imagination, faction, faction_list, is_smashable, loot_matrix_index) = match row {
+			Ok(x) => x,
+			Err(_) => return Ok(()),
+		};
+		self.max_health = life.unwrap_or(0.0) as f32;
+		self.health = self.max_health as u32;
+		self.max_armor = armor.unwrap_or(0.0) as f32;
+		self.armor = self.max_armor as u32;
+		self.max_imagination = imagination.unwrap_or(0.0) as f32;
+		self.imagination = self.max_imagination as u32;
+		// the list is comma separated, and only sometimes filled in
+		let factions: Vec<i32> = faction_list.unwrap_or_default().split(',').filter_map(|x| x.trim().parse().ok()).collect();
+		self.factions = if !factions.is_empty() { factions } else { faction.into_iter().collect() };
+		self.is_smashable = is_smashable.unwrap_or(false);
+		self.loot_matrix_index = loot_matrix_index;
+		Ok(())
+	}
+
+	fn run_service(&self, service: &mut GameObjectService, _game_object: &GameObject) {
+		match service {
+			GameObjectService::IsDead(x) => {
+				x.0 = self.is_dead;
+			}
+			_ => {},
+		}
+	}
+
+	fn on_game_message(&mut self, msg: &ServerGM, game_object: &mut GameObject, _state: &mut State, conn: &mut Connection) -> Res<()> {
+		match msg {
+			ServerGM::RequestDie(x) => self.on_request_die(x, game_object, conn),
+			ServerGM::RequestResurrect { .. } => self.on_request_resurrect(game_object, conn),
+			_ => Ok(()),
+		}
+	}
+
+	fn on_timer(&mut self, name: &str, game_object: &mut GameObject, state: &mut State, _conn: &mut Connection) -> Res<()> {
+		if name == DESTROY_TIMER {
+			state.queue_destroy(game_object.object_id());
+		}
+		Ok(())
+	}
 
-	fn run_service_mut(&mut self, service: &mut GameObjectServiceMut, _game_object: &mut GameObject, _state: &mut State, _conn: &mut Connection) -> Res<()> {
+	fn run_service_mut(&mut self, service: &mut GameObjectServiceMut, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
 		match service {
 			GameObjectServiceMut::SetFaction(set_faction) => self.set_faction(set_faction),
+			GameObjectServiceMut::Damage(damage) => {
+				self.damage(damage)?;
+				if !damage.killed {
+					return Ok(());
+				}
+				// players don't drop anything
+				let spawn_loot = !self.is_player && self.loot_matrix_index.is_some();
+				self.die(Die {
+					client_death: false,
+					spawn_loot,
+					death_type: lu!(""),
+					direction_relative_angle_xz: 0.0,
+					direction_relative_angle_y: 0.0,
+					direction_relative_force: 0.0,
+					kill_type: KillType::Violent,
+					killer_id: damage.source,
+					loot_owner_id: damage.source,
+				}, game_object, conn)?;
+				if spawn_loot {
+					self.drop_loot(damage.source, game_object, state, conn)?;
+				}
+				Ok(())
+			}
+			GameObjectServiceMut::Heal(heal) => self.heal(heal),
+			GameObjectServiceMut::RepairArmor(repair) => self.repair_armor(repair),
+			GameObjectServiceMut::ChangeImagination(change) => self.change_imagination(change),
 			_ => Ok(()),
 		}
 	}
@@ -1,131 +1,538 @@
-use std::io::Result as Res;
+use std::cmp::min;
+use std::io::{Error, ErrorKind::Other, Result as Res};
+
+use diesel::prelude::*;
+use diesel::dsl::{delete, insert_into, update};
+use rusqlite::{Connection as RusqliteConnection, params};
 
 use lu_packets::{
-	lnv,
+	lnv, lu,
 	common::ObjId,
 	raknet::client::replica::inventory::{EquippedItemInfo, InventoryConstruction, InventoryProtocol, InventorySerialization},
-	world::{LuNameValue, Vector3},
+	world::{Lot, LuNameValue, LnvValue, Vector3},
 	world::gm::InventoryType,
-	world::gm::client::{AddItemToInventoryClientSync, LootType},
-	world::gm::server::{GameMessage as ServerGM, EquipInventory},
+	world::gm::client::{AddItemToInventoryClientSync, LootType, RemoveItemFromInventory as ClientRemoveItemFromInventory},
+	world::gm::server::{GameMessage as ServerGM, EquipInventory, MoveItemBetweenInventoryTypes, MoveItemInInventory, PickupItem, RemoveItemFromInventory, UnEquipInventory, UseNonEquipmentItem},
 };
 
-use crate::services::{AddItem, GameObjectServiceMut};
-use crate::state::{Connection, State};
+use crate::models::InventoryItem;
+use crate::services::{AddItem, GameObjectServiceMut, RemoveItem};
+use crate::state::{BITS_PERSISTENT, Connection, State};
 use super::{GameObject, InternalComponent};
 
+/// Inventory types in the order their bags are written to the XML.
+const INVENTORY_TYPES: [InventoryType; 11] = [
+	InventoryType::Default,
+	InventoryType::VaultItems,
+	InventoryType::Bricks,
+	InventoryType::TemporaryItems,
+	InventoryType::Models,
+	InventoryType::TemporaryModels,
+	InventoryType::Behaviors,
+	InventoryType::PropertyDeeds,
+	InventoryType::VendorBuyback,
+	InventoryType::Hidden,
+	InventoryType::VaultModels,
+];
+
+fn inventory_type_from_i32(raw: i32) -> InventoryType {
+	INVENTORY_TYPES.iter().copied().find(|x| *x as i32 == raw).unwrap_or(InventoryType::Default)
+}
+
 struct Item {
 	object_id: ObjId,
+	lot: Lot,
+	count: u32,
+	slot: u32,
+	inv_type: InventoryType,
+	is_equipped: bool,
+}
+
+impl Item {
+	fn from_model(model: &InventoryItem) -> Self {
+		Self {
+			object_id: model.id as ObjId | BITS_PERSISTENT,
+			lot: model.lot,
+			count: model.count as u32,
+			slot: model.slot as u32,
+			inv_type: inventory_type_from_i32(model.inventory_type),
+			is_equipped: model.is_equipped,
+		}
+	}
+
+	fn to_model(&self, char_id: i32) -> InventoryItem {
+		InventoryItem {
+			id: self.db_id(),
+			character_id: char_id,
+			lot: self.lot,
+			count: self.count as i32,
+			slot: self.slot as i32,
+			inventory_type: self.inv_type as i32,
+			is_equipped: self.is_equipped,
+		}
+	}
+
+	fn db_id(&self) -> i64 {
+		(self.object_id & !BITS_PERSISTENT) as i64
+	}
+
+	fn make_equipped_info(&self) -> EquippedItemInfo {
+		EquippedItemInfo {
+			id: self.object_id,
+			lot: self.lot,
+			subkey: None,
+			count: Some(self.count),
+			slot: Some(self.slot),
+			inventory_type: None,
+			extra_info: None,
+			is_bound: true,
+		}
+	}
 }
 
 pub struct InventoryComponent {
+	/// DB ID of the character this inventory belongs to, if any.
+	char_id: Option<i32>,
+	/// Number of slots for each limited bag.
+	bag_sizes: Vec<(InventoryType, u32)>,
 	items: Vec<Item>,
+	equipped_dirty: bool,
 }
 
 impl InventoryComponent {
-	fn add_item(&mut self, add_item: &AddItem, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+	fn bag_size(&self, inv_type: InventoryType) -> u32 {
+		self.bag_sizes.iter().find(|x| x.0 == inv_type).map(|x| x.1).unwrap_or(u32::MAX)
+	}
+
+	fn first_free_slot(&self, inv_type: InventoryType) -> Option<u32> {
+		(0..self.bag_size(inv_type)).find(|slot| !self.items.iter().any(|x| x.inv_type == inv_type && x.slot == *slot))
+	}
+
+	/// Looks up where an item is worn from the cdclient `ItemComponent` table, `None` if it can't be equipped.
+	fn equip_location(lot: Lot, cdclient: &RusqliteConnection) -> Option<String> {
+		let mut stmt = cdclient.prepare("select equipLocation from itemcomponent where id = (select component_id from componentsregistry where id = ? and component_type = 11)").unwrap();
+		let location: Option<String> = stmt.query_row(params![lot], |row| row.get(0)).ok()?;
+		location.filter(|x| !x.is_empty())
+	}
+
+	/// Looks up the cdclient item type, `None` if the LOT is not an item.
+	fn item_type(lot: Lot, cdclient: &RusqliteConnection) -> Option<i32> {
+		let mut stmt = cdclient.prepare("select itemType from itemcomponent where id = (select component_id from componentsregistry where id = ? and component_type = 11)").unwrap();
+		stmt.query_row(params![lot], |row| row.get(0)).ok()?
+	}
+
+	/// Looks up the bag an item goes into and how many fit in one slot.
+	fn item_info(lot: Lot, cdclient: &RusqliteConnection) -> (InventoryType, u32) {
+		let mut stmt = cdclient.prepare("select stackSize from itemcomponent where id = (select component_id from componentsregistry where id = ? and component_type = 11)").unwrap();
+		let stack_size: Option<u32> = stmt.query_row(params![lot], |row| row.get(0)).unwrap_or(None);
+		let inv_type = match Self::item_type(lot, cdclient) {
+			Some(1) => InventoryType::Bricks,
+			Some(10) => InventoryType::Behaviors,
+			Some(11) => InventoryType::PropertyDeeds,
+			Some(12) | Some(21) => InventoryType::Models,
+			_ => InventoryType::Default,
+		};
+		(inv_type, stack_size.unwrap_or(1).max(1))
+	}
+
+	fn save_item(&self, index: usize, state: &State) -> Res<()> {
+		use crate::schema::inventory_items::dsl::{inventory_items, count, slot, inventory_type, is_equipped};
+
+		if self.char_id.is_none() {
+			return Ok(());
+		}
+		let item = &self.items[index];
+		if let Err(e) = update(inventory_items.find(item.db_id()))
+		.set((
+			count.eq(item.count as i32),
+			slot.eq(item.slot as i32),
+			inventory_type.eq(item.inv_type as i32),
+			is_equipped.eq(item.is_equipped),
+		))
+		.execute(state.db()) {
+			return Err(Error::new(Other, format!("Error saving item: {}", e)));
+		}
+		Ok(())
+	}
+
+	fn delete_item(&self, index: usize, state: &State) -> Res<()> {
+		use crate::schema::inventory_items::dsl::inventory_items;
+
+		if self.char_id.is_none() {
+			return Ok(());
+		}
+		if let Err(e) = delete(inventory_items.find(self.items[index].db_id()))
+		.execute(state.db()) {
+			return Err(Error::new(Other, format!("Error deleting item: {}", e)));
+		}
+		Ok(())
+	}
+
+	fn equipped_items(&self) -> Vec<EquippedItemInfo> {
+		self.items.iter().filter(|x| x.is_equipped).map(Item::make_equipped_info).collect()
+	}
+
+	fn send_add_sync(&self, index: usize, added: u32, show_flying_loot: bool, game_object: &GameObject, conn: &mut Connection) -> Res<()> {
+		let item = &self.items[index];
 		let add = AddItemToInventoryClientSync {
 			bound: false,
 			is_boe: false,
 			is_bop: false,
 			loot_type_source: LootType::None,
 			extra_info: lnv! {},
-			obj_template: add_item.lot,
+			obj_template: item.lot,
 			subkey: 0,
-			inv_type: InventoryType::Default,
-			item_count: 1,
-			items_total: 1,
-			new_obj_id: state.new_spawned_id(),
+			inv_type: item.inv_type,
+			item_count: added,
+			items_total: item.count,
+			new_obj_id: item.object_id,
 			flying_loot_posit: Vector3::default(),
-			show_flying_loot: true,
-			slot_id: 2,
+			show_flying_loot,
+			slot_id: item.slot as i32,
 		};
 		let gm = game_object.make_sgm(add);
 		conn.send(gm)
 	}
 
-	fn on_equip_inventory(&mut self, msg: &EquipInventory, _game_object: &mut GameObject, _state: &mut State, _conn: &mut Connection) -> Res<()> {
-		for item in &self.items {
-			if item.object_id == msg.item_to_equip {
+	fn send_remove_sync(&self, index: usize, removed: u32, game_object: &GameObject, conn: &mut Connection) -> Res<()> {
+		let item = &self.items[index];
+		let remove = ClientRemoveItemFromInventory {
+			confirmed: true,
+			delete_item: true,
+			out_success: false,
+			inv_type: item.inv_type,
+			loot_type_source: LootType::None,
+			extra_info: lnv! {},
+			force_deletion: true,
+			loot_type_source_id: 0,
+			obj_id: item.object_id,
+			obj_template: item.lot,
+			requesting_obj_id: 0,
+			stack_count: removed,
+			stack_remaining: item.count - removed,
+			subkey: 0,
+			trade_id: 0,
+		};
+		let gm = game_object.make_sgm(remove);
+		conn.send(gm)
+	}
+
+	fn add_item(&mut self, add_item: &mut AddItem, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		use crate::schema::inventory_items::dsl::inventory_items;
+
+		let (inv_type, stack_size) = Self::item_info(add_item.lot, state.cdclient());
+		let mut remaining = add_item.count;
 
+		// top up existing stacks first
+		for i in 0..self.items.len() {
+			if remaining == 0 {
+				break;
 			}
+			let item = &mut self.items[i];
+			if item.lot != add_item.lot || item.inv_type != inv_type || item.count >= stack_size {
+				continue;
+			}
+			let added = min(stack_size - item.count, remaining);
+			item.count += added;
+			remaining -= added;
+			self.save_item(i, state)?;
+			self.send_add_sync(i, added, true, game_object, conn)?;
+		}
+
+		while remaining > 0 {
+			let slot = match self.first_free_slot(inv_type) {
+				Some(x) => x,
+				None => break,
+			};
+			let added = min(stack_size, remaining);
+			let item = Item {
+				object_id: state.new_persistent_id()? as ObjId | BITS_PERSISTENT,
+				lot: add_item.lot,
+				count: added,
+				slot,
+				inv_type,
+				is_equipped: false,
+			};
+
+			if let Some(char_id) = self.char_id {
+				if let Err(e) = insert_into(inventory_items)
+				.values(&item.to_model(char_id))
+				.execute(state.db()) {
+					return Err(Error::new(Other, format!("Error saving item: {}", e)));
+				}
+			}
+			self.items.push(item);
+			remaining -= added;
+			self.send_add_sync(self.items.len()-1, added, true, game_object, conn)?;
+		}
+
+		add_item.added = add_item.count - remaining;
+		if remaining > 0 {
+			println!("Inventory of {} is full, could not add {} of LOT {}", game_object.object_id(), remaining, add_item.lot);
+		}
+		Ok(())
+	}
+
+	/// Removes up to `count` from the stack at `index`, deleting the item once the stack is empty. Returns how many were removed.
+	fn remove_from_stack(&mut self, index: usize, count: u32, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<u32> {
+		let removed = min(count, self.items[index].count);
+		self.send_remove_sync(index, removed, game_object, conn)?;
+		self.items[index].count -= removed;
+		if self.items[index].count > 0 {
+			self.save_item(index, state)?;
+			return Ok(removed);
+		}
+		self.delete_item(index, state)?;
+		let item = self.items.remove(index);
+		if item.is_equipped {
+			self.equipped_dirty = true;
 		}
+		Ok(removed)
+	}
+
+	fn remove_item(&mut self, remove_item: &mut RemoveItem, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		let mut remaining = remove_item.count;
+		while remaining > 0 {
+			let index = match self.items.iter().position(|x| if remove_item.object_id != 0 { x.object_id == remove_item.object_id } else { x.lot == remove_item.lot }) {
+				Some(x) => x,
+				None => break,
+			};
+			remaining -= self.remove_from_stack(index, remaining, game_object, state, conn)?;
+		}
+		remove_item.removed = remove_item.count - remaining;
 		Ok(())
 	}
+
+	fn on_remove_item_from_inventory(&mut self, msg: &RemoveItemFromInventory, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		if !msg.confirmed || !msg.delete_item {
+			return Ok(());
+		}
+		let index = match self.items.iter().position(|x| x.object_id == msg.obj_id) {
+			Some(x) => x,
+			None => {
+				eprintln!("Item {} to remove is not in the inventory!", msg.obj_id);
+				return Ok(());
+			}
+		};
+		self.remove_from_stack(index, msg.stack_count, game_object, state, conn)?;
+		Ok(())
+	}
+
+	fn on_use_non_equipment_item(&mut self, msg: &UseNonEquipmentItem, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		let index = match self.items.iter().position(|x| x.object_id == msg.item_to_use) {
+			Some(x) => x,
+			None => {
+				eprintln!("Item {} to use is not in the inventory!", msg.item_to_use);
+				return Ok(());
+			}
+		};
+		// consumable
+		if Self::item_type(self.items[index].lot, state.cdclient()) != Some(14) {
+			return Ok(());
+		}
+		let mut remove_item = RemoveItem { object_id: msg.item_to_use, lot: self.items[index].lot, count: 1, removed: 0 };
+		self.remove_item(&mut remove_item, game_object, state, conn)
+	}
+
+	fn on_pickup_item(&mut self, msg: &PickupItem, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		let lot = match state.take_loot(msg.loot_object_id, game_object.object_id()) {
+			Some(x) => x,
+			None => {
+				eprintln!("Loot {} to pick up was not dropped for {}!", msg.loot_object_id, game_object.object_id());
+				return Ok(());
+			}
+		};
+		let mut add_item = AddItem { lot, count: 1, added: 0 };
+		self.add_item(&mut add_item, game_object, state, conn)
+	}
+
+	fn on_equip_inventory(&mut self, msg: &EquipInventory, _game_object: &mut GameObject, state: &mut State, _conn: &mut Connection) -> Res<()> {
+		let index = match self.items.iter().position(|x| x.object_id == msg.item_to_equip) {
+			Some(x) => x,
+			None => {
+				eprintln!("Item {} to equip is not in the inventory!", msg.item_to_equip);
+				return Ok(());
+			}
+		};
+		if self.items[index].is_equipped {
+			return Ok(());
+		}
+		let location = match Self::equip_location(self.items[index].lot, state.cdclient()) {
+			Some(x) => x,
+			None => {
+				eprintln!("Item {} with LOT {} can't be equipped!", msg.item_to_equip, self.items[index].lot);
+				return Ok(());
+			}
+		};
+
+		// only one item per equip location
+		for i in 0..self.items.len() {
+			if i != index && self.items[i].is_equipped && Self::equip_location(self.items[i].lot, state.cdclient()).as_ref() == Some(&location) {
+				self.items[i].is_equipped = false;
+				self.save_item(i, state)?;
+			}
+		}
+
+		self.items[index].is_equipped = true;
+		self.save_item(index, state)?;
+		self.equipped_dirty = true;
+		Ok(())
+	}
+
+	fn on_unequip_inventory(&mut self, msg: &UnEquipInventory, _game_object: &mut GameObject, state: &mut State, _conn: &mut Connection) -> Res<()> {
+		let index = match self.items.iter().position(|x| x.object_id == msg.item_to_unequip) {
+			Some(x) => x,
+			None => {
+				eprintln!("Item {} to unequip is not in the inventory!", msg.item_to_unequip);
+				return Ok(());
+			}
+		};
+		if !self.items[index].is_equipped {
+			return Ok(());
+		}
+		self.items[index].is_equipped = false;
+		self.save_item(index, state)?;
+		self.equipped_dirty = true;
+		Ok(())
+	}
+
+	fn on_move_item_in_inventory(&mut self, msg: &MoveItemInInventory, _game_object: &mut GameObject, state: &mut State, _conn: &mut Connection) -> Res<()> {
+		let index = match self.items.iter().position(|x| x.object_id == msg.obj_id) {
+			Some(x) => x,
+			None => {
+				eprintln!("Item {} to move is not in the inventory!", msg.obj_id);
+				return Ok(());
+			}
+		};
+		let inv_type = self.items[index].inv_type;
+		let dest_slot = msg.slot as u32;
+		if msg.slot < 0 || dest_slot >= self.bag_size(inv_type) {
+			eprintln!("Slot {} is out of range for inventory type {:?}!", msg.slot, inv_type);
+			return Ok(());
+		}
+
+		// swap with the item already in the slot, if any
+		if let Some(other) = self.items.iter().position(|x| x.inv_type == inv_type && x.slot == dest_slot) {
+			self.items[other].slot = self.items[index].slot;
+			self.save_item(other, state)?;
+		}
+		self.items[index].slot = dest_slot;
+		self.save_item(index, state)
+	}
+
+	fn on_move_item_between_inventory_types(&mut self, msg: &MoveItemBetweenInventoryTypes, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		let index = match self.items.iter().position(|x| x.inv_type == msg.inventory_type_a && (x.object_id == msg.object_id || (msg.object_id == 0 && x.lot == msg.template_id))) {
+			Some(x) => x,
+			None => {
+				eprintln!("Item {} to move is not in inventory type {:?}!", msg.object_id, msg.inventory_type_a);
+				return Ok(());
+			}
+		};
+		let slot = match self.first_free_slot(msg.inventory_type_b) {
+			Some(x) => x,
+			None => {
+				println!("Inventory type {:?} of {} is full, could not move item {}", msg.inventory_type_b, game_object.object_id(), self.items[index].object_id);
+				return Ok(());
+			}
+		};
+
+		let count = self.items[index].count;
+		self.send_remove_sync(index, count, game_object, conn)?;
+		let item = &mut self.items[index];
+		item.inv_type = msg.inventory_type_b;
+		item.slot = slot;
+		if item.is_equipped {
+			item.is_equipped = false;
+			self.equipped_dirty = true;
+		}
+		self.save_item(index, state)?;
+		self.send_add_sync(index, count, msg.show_flying_loot, game_object, conn)
+	}
 }
 
 impl InternalComponent for InventoryComponent {
 	type ComponentProtocol = InventoryProtocol;
 
-	fn new(_config: &LuNameValue) -> Self {
-		Self { items: vec![] }
+	fn new(config: &LuNameValue) -> Self {
+		let char_id = if let Some(LnvValue::I32(x)) = config.get(&lu!("char_id")) { Some(*x) } else { None };
+
+		Self { char_id, bag_sizes: vec![], items: vec![], equipped_dirty: false }
 	}
 
 	fn make_construction(&self) -> InventoryConstruction {
 		InventoryConstruction {
-			equipped_items: Some(vec![
-				EquippedItemInfo {
-					id: 1152921510436607008,
-					lot: 4106,
-					subkey: None,
-					count: Some(1),
-					slot: None,
-					inventory_type: None,
-					extra_info: None,
-					is_bound: true,
-				},
-				EquippedItemInfo {
-					id: 1152921510436607009,
-					lot: 2524,
-					subkey: None,
-					count: Some(1),
-					slot: Some(1),
-					inventory_type: None,
-					extra_info: None,
-					is_bound: true,
-				},
-			].into()),
+			equipped_items: Some(self.equipped_items().into()),
 			equipped_model_transforms: None,
 		}
 	}
 
 	fn make_serialization(&self) -> InventorySerialization {
 		InventorySerialization {
-			equipped_items: Some(vec![
-				EquippedItemInfo {
-					id: 1152921510436607008,
-					lot: 4106,
-					subkey: None,
-					count: Some(1),
-					slot: None,
-					inventory_type: None,
-					extra_info: None,
-					is_bound: true,
-				},
-				EquippedItemInfo {
-					id: 1152921510436607009,
-					lot: 2524,
-					subkey: None,
-					count: Some(1),
-					slot: Some(1),
-					inventory_type: None,
-					extra_info: None,
-					is_bound: true,
-				},
-			].into()),
+			equipped_items: if !self.equipped_dirty { None } else { Some(self.equipped_items().into()) },
 			equipped_model_transforms: None,
 		}
 	}
 
+	fn is_dirty(&self) -> bool {
+		self.equipped_dirty
+	}
+
+	fn clear_dirty(&mut self) {
+		self.equipped_dirty = false;
+	}
+
 	fn write_xml(&self, writer: &mut String) -> std::fmt::Result {
 		use std::fmt::Write;
-		write!(writer, "<inv><items><in t=\"0\"><i l=\"4106\" id=\"1152921510436607008\" s=\"0\" eq=\"1\"/><i l=\"2524\" id=\"1152921510436607009\" s=\"1\" eq=\"1\"/></in></items></inv>")?;
+		write!(writer, "<inv><bag>")?;
+		for (inv_type, size) in &self.bag_sizes {
+			write!(writer, "<b t=\"{}\" m=\"{}\"/>", *inv_type as u32, size)?;
+		}
+		write!(writer, "</bag><items>")?;
+		for inv_type in &INVENTORY_TYPES {
+			if !self.items.iter().any(|x| x.inv_type == *inv_type) {
+				continue;
+			}
+			write!(writer, "<in t=\"{}\">", *inv_type as u32)?;
+			for item in self.items.iter().filter(|x| x.inv_type == *inv_type) {
+				write!(writer, "<i l=\"{}\" id=\"{}\" s=\"{}\" c=\"{}\" eq=\"{}\"/>", item.lot, item.object_id, item.slot, item.count, item.is_equipped as u8)?;
+			}
+			write!(writer, "</in>")?;
+		}
+		write!(writer, "</items></inv>")?;
+		Ok(())
+	}
+
+	fn load(&mut self, _game_object: &GameObject, state: &State) -> Res<()> {
+		use crate::schema::inventory_items::dsl::{inventory_items, character_id};
+
+		let conf = state.inventory_conf();
+		self.bag_sizes = vec![
+			(InventoryType::Default, conf.items),
+			(InventoryType::VaultItems, conf.vault_items),
+			(InventoryType::Bricks, conf.bricks),
+			(InventoryType::Models, conf.models),
+			(InventoryType::VaultModels, conf.vault_models),
+		];
+
+		let char_id = match self.char_id {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+
+		let models: Vec<InventoryItem> = match inventory_items
+		.filter(character_id.eq(char_id))
+		.load(state.db()) {
+			Ok(x) => x,
+			Err(e) => return Err(Error::new(Other, format!("Error loading items: {}", e))),
+		};
+		self.items = models.iter().map(Item::from_model).collect();
 		Ok(())
 	}
 
 	fn run_service_mut(&mut self, service: &mut GameObjectServiceMut, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
 		match service {
 			GameObjectServiceMut::AddItem(add_item) => self.add_item(add_item, game_object, state, conn),
+			GameObjectServiceMut::RemoveItem(remove_item) => self.remove_item(remove_item, game_object, state, conn),
 			_ => Ok(()),
 		}
 	}
@@ -133,6 +540,12 @@ impl InternalComponent for InventoryComponent {
 	fn on_game_message(&mut self, msg: &ServerGM, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
 		match msg {
 			ServerGM::EquipInventory(x) => self.on_equip_inventory(x, game_object, state, conn),
+			ServerGM::UnEquipInventory(x) => self.on_unequip_inventory(x, game_object, state, conn),
+			ServerGM::MoveItemInInventory(x) => self.on_move_item_in_inventory(x, game_object, state, conn),
+			ServerGM::MoveItemBetweenInventoryTypes(x) => self.on_move_item_between_inventory_types(x, game_object, state, conn),
+			ServerGM::RemoveItemFromInventory(x) => self.on_remove_item_from_inventory(x, game_object, state, conn),
+			ServerGM::UseNonEquipmentItem(x) => self.on_use_non_equipment_item(x, game_object, state, conn),
+			ServerGM::PickupItem(x) => self.on_pickup_item(x, game_object, state, conn),
 			_ => Ok(()),
 		}
 	}
@@ -19,15 +19,15 @@ use rusqlite::{Connection as RusqliteConnection, params};
 
 use lu_packets::{
 	lu,
-	common::{LuVarWString, ObjId},
-	raknet::client::replica::{ComponentConstruction, ComponentProtocol, ComponentSerialization, ReplicaConstruction, ReplicaSerialization},
-	world::{Lot, LuNameValue},
+	common::{LuStrExt, LuVarWString, ObjId},
+	raknet::client::replica::{ComponentConstruction, ComponentProtocol, ComponentSerialization, ReplicaConstruction, ReplicaDestruction, ReplicaSerialization},
+	world::{Lot, LuNameValue, LnvValue, Vector3},
 	world::gm::client::{SubjectGameMessage as ClientSGM, GameMessage as ClientGM},
 	world::gm::server::GameMessage as ServerGM,
 };
 
 use crate::state::Connection;
-use crate::services::{GameObjectService, GameObjectServiceMut};
+use crate::services::{GameObjectService, GameObjectServiceMut, GetPosition};
 use crate::state::State;
 use self::base_combat_ai::BaseCombatAiComponent;
 use self::bbb::BbbComponent;
@@ -53,9 +53,17 @@ trait InternalComponent {
 	fn write_xml(&self, _writer: &mut String) -> std::fmt::Result {
 		Ok(())
 	}
+	fn load(&mut self, _game_object: &GameObject, _state: &State) -> Res<()> { Ok(()) }
 	fn on_game_message(&mut self, _msg: &ServerGM, _game_object: &mut GameObject, _state: &mut State, _conn: &mut Connection) -> Res<()> { Ok(()) }
 	fn run_service(&self, _service: &mut GameObjectService, _game_object: &GameObject) {}
 	fn run_service_mut(&mut self, _service: &mut GameObjectServiceMut, _game_object: &mut GameObject, _state: &mut State, _conn: &mut Connection) -> Res<()> { Ok(()) }
+	/// Called every server tick, `dt` is the time since the last tick in seconds.
+	fn update(&mut self, _dt: f32, _game_object: &mut GameObject, _state: &mut State, _conn: &mut Connection) -> Res<()> { Ok(()) }
+	/// Called when a timer added with `GameObject::add_timer` runs out.
+	fn on_timer(&mut self, _name: &str, _game_object: &mut GameObject, _state: &mut State, _conn: &mut Connection) -> Res<()> { Ok(()) }
+	/// Whether any state included in the serialization changed since the last one was sent.
+	fn is_dirty(&self) -> bool { false }
+	fn clear_dirty(&mut self) {}
 }
 
 trait Component {
@@ -63,9 +71,14 @@ trait Component {
 	fn make_construction(&self) -> Box<dyn ComponentConstruction>;
 	fn make_serialization(&self) -> Box<dyn ComponentSerialization>;
 	fn write_xml(&self, _writer: &mut String) -> std::fmt::Result;
+	fn load(&mut self, _game_object: &GameObject, _state: &State) -> Res<()>;
 	fn on_game_message(&mut self, _msg: &ServerGM, _game_object: &mut GameObject, _state: &mut State, _conn: &mut Connection) -> Res<()>;
 	fn run_service(&self, _service: &mut GameObjectService, _game_object: &GameObject);
 	fn run_service_mut(&mut self, _service: &mut GameObjectServiceMut, _game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()>;
+	fn update(&mut self, _dt: f32, _game_object: &mut GameObject, _state: &mut State, _conn: &mut Connection) -> Res<()>;
+	fn on_timer(&mut self, _name: &str, _game_object: &mut GameObject, _state: &mut State, _conn: &mut Connection) -> Res<()>;
+	fn is_dirty(&self) -> bool;
+	fn clear_dirty(&mut self);
 }
 
 impl<I: 'static+InternalComponent> Component for I {
@@ -85,6 +98,10 @@ impl<I: 'static+InternalComponent> Component for I {
 		<I as InternalComponent>::write_xml(self, writer)
 	}
 
+	fn load(&mut self, game_object: &GameObject, state: &State) -> Res<()> {
+		<I as InternalComponent>::load(self, game_object, state)
+	}
+
 	fn on_game_message(&mut self, msg: &ServerGM, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
 		<I as InternalComponent>::on_game_message(self, msg, game_object, state, conn)
 	}
@@ -96,6 +113,22 @@ impl<I: 'static+InternalComponent> Component for I {
 	fn run_service_mut(&mut self, service: &mut GameObjectServiceMut, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
 		<I as InternalComponent>::run_service_mut(self, service, game_object, state, conn)
 	}
+
+	fn update(&mut self, dt: f32, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		<I as InternalComponent>::update(self, dt, game_object, state, conn)
+	}
+
+	fn on_timer(&mut self, name: &str, game_object: &mut GameObject, state: &mut State, conn: &mut Connection) -> Res<()> {
+		<I as InternalComponent>::on_timer(self, name, game_object, state, conn)
+	}
+
+	fn is_dirty(&self) -> bool {
+		<I as InternalComponent>::is_dirty(self)
+	}
+
+	fn clear_dirty(&mut self) {
+		<I as InternalComponent>::clear_dirty(self)
+	}
 }
 
 pub struct GameObject {
@@ -103,7 +136,14 @@ pub struct GameObject {
 	object_id: ObjId,
 	lot: Lot,
 	name: LuVarWString<u8>,
+	scale: Option<f32>,
+	spawner_id: Option<ObjId>,
+	spawner_node_id: Option<u32>,
+	/// Position from the spawn config, for objects without a physics component.
+	config_position: Option<Vector3>,
 	components: Vec<Box<dyn Component>>,
+	/// Running timers, with their names and remaining time in seconds.
+	timers: Vec<(String, f32)>,
 }
 
 const COMP_ORDER: [u32; 35] = [108, 61, 1, 30, 20, 3, 40, 98, 7, 110, 109, 106, 4, 26, 17, 5, 9, 60, 11, 48, 25, 16, 100, 102, 19, 39, 23, 75, 42, 6, 49, 2, 44, 71, 107];
@@ -122,13 +162,41 @@ impl GameObject {
 		Self::apply_component_overrides(&comps, &mut final_comps);
 
 		let components = Self::create_components(&final_comps, config)?;
+		let mut name = match config.get(&lu!("name")) {
+			Some(LnvValue::WString(x)) => x.to_string(),
+			_ => String::new(),
+		};
+		if name.is_empty() {
+			name = format!("{}", object_id);
+		}
+		let scale = match config.get(&lu!("scale")) {
+			Some(LnvValue::F32(x)) if *x != 1.0 => Some(*x),
+			_ => None,
+		};
+		let config_position = match (config.get(&lu!("position_x")), config.get(&lu!("position_y")), config.get(&lu!("position_z"))) {
+			(Some(LnvValue::F32(x)), Some(LnvValue::F32(y)), Some(LnvValue::F32(z))) => Some(Vector3 { x: *x, y: *y, z: *z }),
+			_ => None,
+		};
+		let spawner_id = match config.get(&lu!("spawner_id")) {
+			Some(LnvValue::I64(x)) => Some(*x as ObjId),
+			_ => None,
+		};
+		let spawner_node_id = match config.get(&lu!("spawner_node_id")) {
+			Some(LnvValue::U32(x)) => Some(*x),
+			_ => None,
+		};
 
 		Ok(Self {
 			network_id,
 			object_id,
 			lot,
-			name: lu!(&format!("{}", object_id)[..]),
+			name: lu!(&name[..]),
+			scale,
+			spawner_id,
+			spawner_node_id,
+			config_position,
 			components,
+			timers: vec![],
 		})
 	}
 
@@ -186,14 +254,52 @@ impl GameObject {
 		Ok(components)
 	}
 
+	pub fn network_id(&self) -> u16 {
+		return self.network_id;
+	}
+
 	pub fn object_id(&self) -> ObjId {
 		return self.object_id;
 	}
 
+	pub fn lot(&self) -> Lot {
+		return self.lot;
+	}
+
+	/// Current position, from physics if the object has it, `None` if the object wasn't spawned with a position.
+	pub fn position(&self) -> Option<Vector3> {
+		let mut get_pos = GetPosition(self.config_position?);
+		self.run_service(&mut get_pos);
+		Some(get_pos.0)
+	}
+
 	pub fn name(&self) -> &LuVarWString<u8> {
 		return &self.name;
 	}
 
+	/// Whether a component changed state that needs to be serialized to clients.
+	pub fn is_dirty(&self) -> bool {
+		self.components.iter().any(|x| x.is_dirty())
+	}
+
+	/// Starts a timer, components get an `on_timer` call with its name once it runs out.
+	///
+	/// Adding a timer with the name of a running one restarts it.
+	pub fn add_timer(&mut self, name: &str, seconds: f32) {
+		self.cancel_timer(name);
+		self.timers.push((name.to_string(), seconds));
+	}
+
+	pub fn cancel_timer(&mut self, name: &str) {
+		self.timers.retain(|(x, _)| x != name);
+	}
+
+	pub fn clear_dirty(&mut self) {
+		for comp in &mut self.components {
+			comp.clear_dirty();
+		}
+	}
+
 	pub fn make_construction(&self) -> ReplicaConstruction {
 		let mut comp_constructions = vec![];
 
@@ -205,13 +311,13 @@ impl GameObject {
 			network_id: self.network_id,
 			object_id: self.object_id,
 			lot: self.lot,
-			name: lu!("Todd Howard"),
+			name: self.name.clone(),
 			time_since_created_on_server: 0,
 			config: None,
 			is_trigger: false,
-			spawner_id: None,
-			spawner_node_id: None,
-			scale: None,
+			spawner_id: self.spawner_id,
+			spawner_node_id: self.spawner_node_id,
+			scale: self.scale,
 			world_state: None,
 			gm_level: None,
 			parent_child_info: None,
@@ -233,6 +339,12 @@ impl GameObject {
 		}
 	}
 
+	pub fn make_destruction(&self) -> ReplicaDestruction {
+		ReplicaDestruction {
+			network_id: self.network_id,
+		}
+	}
+
 	pub fn write_xml(&self, writer: &mut String) -> std::fmt::Result {
 		use std::fmt::Write;
 		write!(writer, "<obj v=\"1\">")?;
@@ -267,12 +379,42 @@ impl GameObject {
 		Ok(())
 	}
 
+	/// Lets components load their persistent data, called once after creation.
+	pub fn load(&mut self, state: &State) -> Res<()> {
+		self.iter_comps(|game_object, comp| {
+			comp.load(game_object, state)
+		})
+	}
+
 	pub fn on_game_message(&mut self, msg: &ServerGM, state: &mut State, conn: &mut Connection) -> Res<()> {
 		dbg!(msg);
 
 		self.iter_comps(|game_object, comp| {
 			comp.on_game_message(msg, game_object, state, conn)
-		})
+		})?;
+		state.serialize(self, None, conn)
+	}
+
+	/// Advances components and timers by `dt` seconds.
+	pub fn update(&mut self, dt: f32, state: &mut State, conn: &mut Connection) -> Res<()> {
+		self.iter_comps(|game_object, comp| {
+			comp.update(dt, game_object, state, conn)
+		})?;
+
+		let mut expired = vec![];
+		for (name, remaining) in &mut self.timers {
+			*remaining -= dt;
+			if *remaining <= 0.0 {
+				expired.push(name.clone());
+			}
+		}
+		self.timers.retain(|(_, remaining)| *remaining > 0.0);
+		for name in expired {
+			self.iter_comps(|game_object, comp| {
+				comp.on_timer(&name, game_object, state, conn)
+			})?;
+		}
+		state.serialize(self, None, conn)
 	}
 
 	pub fn run_service<'a, S: Into<GameObjectService<'a>>>(&self, service: S) {
@@ -288,7 +430,11 @@ impl GameObject {
 		self.iter_comps(|game_object, comp| {
 			comp.run_service_mut(&mut go_service, game_object, state, conn)
 		})?;
-		let ser = self.make_serialization();
-		conn.broadcast(ser)
+		// the client sending its position already knows it
+		let exclude = match go_service {
+			GameObjectServiceMut::SetFrameStats(_) => Some(conn.peer_addr().unwrap()),
+			_ => None,
+		};
+		state.serialize(self, exclude, conn)
 	}
 }
\ No newline at end of file
@@ -1,33 +1,48 @@
 //! Message listeners for Character selection, creation, deletion, and login.
+use std::cmp::Reverse;
 use std::convert::TryInto;
 use std::io::{Error, ErrorKind::Other, Result as Res};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use diesel::prelude::*;
-use diesel::dsl::{delete, insert_into};
+use diesel::dsl::{delete, insert_into, update};
 
 use lu_packets::{
+	common::ObjId,
+	general::client::DisconnectNotify,
 	world::{Vector3, ZoneId},
-	world::client::{CharListChar, CharacterListResponse, CharacterCreateResponse, CharacterDeleteResponse, InstanceType, LoadStaticZone},
-	world::server::{CharacterCreateRequest, CharacterDeleteRequest, CharacterLoginRequest},
+	world::client::{CharListChar, CharacterListResponse, CharacterCreateResponse, CharacterDeleteResponse, CharacterRenameResponse, InstanceType, LoadStaticZone, TransferToWorld},
+	world::server::{CharacterCreateRequest, CharacterDeleteRequest, CharacterLoginRequest, CharacterRenameRequest},
 };
 
-use crate::state::{AccountInfo, Connection, State};
-use crate::models::Character;
+use crate::WorldConf;
+use crate::state::{AccountInfo, BITS_PERSISTENT, Connection, State};
+use crate::models::{Character, InventoryItem};
 
 pub fn on_char_list_req(state: &State, acc_info: &AccountInfo, conn: &mut Connection) -> Res<()> {
-	use crate::schema::characters::dsl::{characters, username};
+	use crate::schema::characters::dsl::{characters, username, deleted_at};
+	use crate::schema::inventory_items::dsl::{inventory_items, character_id, is_equipped, lot};
+
+	purge_expired_chars(state, acc_info.username())?;
 
 	let chars: Vec<Character> = characters
 	.filter(username.eq(&acc_info.username()))
+	.filter(deleted_at.is_null())
 	.load(state.db()).expect("Error loading characters");
 	let mut list_chars = vec![];
 
-	for chara in chars {
+	for chara in &chars {
+		let equipped: Vec<i32> = inventory_items
+		.filter(character_id.eq(chara.id))
+		.filter(is_equipped.eq(true))
+		.select(lot)
+		.load(state.db()).expect("Error loading equipped items");
+
 		list_chars.push(CharListChar {
-			obj_id: (chara.id as u64) | (1 << 60),
+			obj_id: (chara.id as ObjId) | BITS_PERSISTENT,
 			char_name: (&*chara.name).try_into().unwrap(),
-			pending_name: "".try_into().unwrap(),
-			requires_rename: false,
+			pending_name: (&*chara.pending_name.clone().unwrap_or_default()).try_into().unwrap(),
+			requires_rename: !chara.name_approved && chara.pending_name.is_none(),
 			is_free_trial: false,
 			torso_color: chara.torso_color as u32,
 			legs_color: chara.legs_color as u32,
@@ -37,23 +52,143 @@ pub fn on_char_list_req(state: &State, acc_info: &AccountInfo, conn: &mut Connec
 			eyes_style: chara.eyes_style as u32,
 			mouth_style: chara.mouth_style as u32,
 			last_location: ZoneId { map_id: chara.world_zone as u16, instance_id: chara.world_instance as u16, clone_id: chara.world_clone as u32 },
-			equipped_items: vec![].into(),
+			equipped_items: equipped.into(),
 		});
 	}
 
+	// preselect the character played last, the first one if none have been played
+	let selected_char = chars.iter().enumerate().max_by_key(|(i, x)| (x.last_login, Reverse(*i))).map(|(i, _)| i as u8).unwrap_or(0);
+
 	conn.send(CharacterListResponse {
-		selected_char: 0,
+		selected_char,
 		chars: list_chars,
 	})
 }
 
+/// Maximum number of characters per account.
+pub const MAX_CHARACTERS: i64 = 4;
+
+fn unix_now() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+/// Deletes a character along with everything it owns.
+fn purge_char(state: &State, char_id: i32) -> Res<()> {
+	use crate::schema::characters::dsl::characters;
+	use crate::schema::inventory_items::dsl::{inventory_items, character_id};
+
+	// any other table keyed on the character has to be cleaned up here as well
+	let res = state.db().transaction::<_, diesel::result::Error, _>(|| {
+		delete(inventory_items.filter(character_id.eq(char_id))).execute(state.db())?;
+		delete(characters.find(char_id)).execute(state.db())?;
+		Ok(())
+	});
+	match res {
+		Ok(()) => Ok(()),
+		Err(e) => Err(Error::new(Other, format!("Error deleting character {}: {}", char_id, e))),
+	}
+}
+
+/// Timestamp before which deleted characters can no longer be restored.
+pub fn restore_cutoff(state: &State) -> i64 {
+	unix_now() - state.character_conf().delete_grace_days as i64 * 24 * 60 * 60
+}
+
+/// Permanently deletes the account's characters whose restore window is over.
+fn purge_expired_chars(state: &State, acc_username: &str) -> Res<()> {
+	use crate::schema::characters::dsl::{characters, id, username, deleted_at};
+
+	let expired: Vec<i32> = characters
+	.filter(username.eq(acc_username))
+	.filter(deleted_at.lt(restore_cutoff(state)))
+	.select(id)
+	.load(state.db()).expect("Error loading deleted characters");
+	for char_id in expired {
+		purge_char(state, char_id)?;
+	}
+	Ok(())
+}
+
+/// Checks that a custom name has an acceptable length and only uses letters and digits.
+fn is_valid_name(name: &str) -> bool {
+	let len = name.chars().count();
+	len >= 3 && len <= 20 && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Checks whether a name is already used by a character, or pending approval for one.
+fn is_name_taken(state: &State, new_name: &str) -> bool {
+	use crate::schema::characters::dsl::{characters, name, pending_name};
+
+	let used: i64 = characters
+	.filter(name.eq(new_name))
+	.count()
+	.get_result(state.db()).expect("Error checking name");
+	let pending: i64 = characters
+	.filter(pending_name.eq(new_name))
+	.count()
+	.get_result(state.db()).expect("Error checking name");
+	used + pending > 0
+}
+
 pub fn on_char_create_req(state: &mut State, msg: &CharacterCreateRequest, acc_info: &AccountInfo, conn: &mut Connection) -> Res<()> {
-	use crate::schema::characters::dsl::{characters};
+	use crate::schema::characters::dsl::{characters, username, deleted_at};
+	use crate::schema::inventory_items::dsl::{inventory_items};
+
+	let char_count: i64 = characters
+	.filter(username.eq(&acc_info.username()))
+	.filter(deleted_at.is_null())
+	.count()
+	.get_result(state.db()).expect("Error counting characters");
+	if char_count >= MAX_CHARACTERS {
+		println!("{} tried to create more than {} characters", acc_info.username(), MAX_CHARACTERS);
+		return conn.send(CharacterCreateResponse::GeneralFailure);
+	}
+
+	let (first, middle, last) = msg.predef_name_ids;
+	let predef_name = match state.predef_names().get(first, middle, last) {
+		Some(x) => x,
+		None => {
+			println!("Invalid predefined name IDs {:?}", msg.predef_name_ids);
+			return conn.send(CharacterCreateResponse::GeneralFailure);
+		}
+	};
+	if is_name_taken(state, &predef_name) {
+		return conn.send(CharacterCreateResponse::PredefinedNameInUse);
+	}
+
+	// custom names are only used once a moderator approves them
+	let custom_name = String::from(&msg.char_name);
+	let pending = if custom_name.is_empty() || custom_name == predef_name {
+		None
+	} else {
+		if !is_valid_name(&custom_name) {
+			return conn.send(CharacterCreateResponse::NameNotAllowed);
+		}
+		if is_name_taken(state, &custom_name) {
+			return conn.send(CharacterCreateResponse::CustomNameInUse);
+		}
+		Some(custom_name)
+	};
+
+	// starter shirt and pants
+	let starter_lots = [4106, 2524];
+	// one ID for the character, one for each starter item
+	let mut ids = vec![];
+	for _ in 0..=starter_lots.len() {
+		match state.new_persistent_id() {
+			Ok(x) => ids.push(x),
+			Err(e) => {
+				eprintln!("{}", e);
+				return conn.send(CharacterCreateResponse::GeneralFailure);
+			}
+		}
+	}
+	let char_id = ids[0] as i32;
 
 	let new_char = Character {
-		id: state.new_persistent_id() as i32,
+		id: char_id,
 		username: acc_info.username().to_string(),
-		name: String::from(&msg.char_name),
+		name: predef_name,
 		torso_color: msg.torso_color as i32,
 		legs_color: msg.legs_color as i32,
 		hair_style: msg.hair_style as i32,
@@ -64,41 +199,208 @@ pub fn on_char_create_req(state: &mut State, msg: &CharacterCreateRequest, acc_i
 		world_zone: 0,
 		world_instance: 0,
 		world_clone: 0,
+		pos_x: None,
+		pos_y: None,
+		pos_z: None,
+		rot_x: None,
+		rot_y: None,
+		rot_z: None,
+		rot_w: None,
+		name_approved: pending.is_none(),
+		pending_name: pending,
+		last_login: 0,
+		deleted_at: None,
+		is_online: false,
 	};
 
-	if let Err(e) = insert_into(characters)
-	.values(&new_char)
-	.execute(state.db()) {
-		conn.send(CharacterCreateResponse::GeneralFailure)?;
-		return Err(Error::new(Other, format!("Error saving character: {}", e)));
+	let mut starter_items = vec![];
+	for (slot, lot) in starter_lots.iter().enumerate() {
+		starter_items.push(InventoryItem {
+			id: ids[slot + 1],
+			character_id: new_char.id,
+			lot: *lot,
+			count: 1,
+			slot: slot as i32,
+			inventory_type: 0,
+			is_equipped: true,
+		});
+	}
+
+	// the character is only created along with its items
+	let res = state.db().transaction::<_, diesel::result::Error, _>(|| {
+		insert_into(characters).values(&new_char).execute(state.db())?;
+		insert_into(inventory_items).values(&starter_items).execute(state.db())?;
+		Ok(())
+	});
+	if let Err(e) = res {
+		eprintln!("Error saving character {}: {}", new_char.name, e);
+		return conn.send(CharacterCreateResponse::GeneralFailure);
 	}
 
 	conn.send(CharacterCreateResponse::Success)?;
 	on_char_list_req(state, acc_info, conn)
 }
 
-pub fn on_char_login_req(_state: &State, _msg: &CharacterLoginRequest, _acc_info: &AccountInfo, conn: &mut Connection) -> Res<()> {
+pub fn on_char_login_req(state: &State, msg: &CharacterLoginRequest, acc_info: &mut AccountInfo, conn: &mut Connection) -> Res<()> {
+	use crate::schema::characters::dsl::{characters, id, username, world_zone, world_instance, world_clone, last_login, deleted_at};
+
+	if acc_info.active_character_id() != 0 {
+		println!("{} tried to log in as character {} while already playing {}!", acc_info.username(), msg.char_id, acc_info.active_character_id());
+		return Ok(());
+	}
+	let chara: Option<Character> = characters
+	.filter(username.eq(&acc_info.username()))
+	.filter(id.eq((msg.char_id & !BITS_PERSISTENT) as i32))
+	.filter(deleted_at.is_null())
+	.first(state.db()).optional().expect("Error loading character");
+
+	let chara = match chara {
+		Some(x) => x,
+		None => {
+			println!("{} tried to log in as character {} which is not theirs!", acc_info.username(), msg.char_id);
+			conn.send(DisconnectNotify::CharacterNotFound)?;
+			conn.close_conn();
+			return Ok(());
+		}
+	};
+	if state.is_char_online(chara.id) {
+		println!("{} tried to log in as character {} which is already logged in", acc_info.username(), msg.char_id);
+		conn.send(DisconnectNotify::DuplicateLogin)?;
+		conn.close_conn();
+		return Ok(());
+	}
+	acc_info.select_char(chara.id);
+
+	let mut player_position = state.spawn_position();
+	if let (Some(x), Some(y), Some(z)) = (chara.pos_x, chara.pos_y, chara.pos_z) {
+		player_position = Vector3 { x, y, z };
+	}
+
+	if let Some(world) = state.world_for_zone(chara.world_zone as u16) {
+		println!("Sending {} to zone {} at {}:{}", chara.name, chara.world_zone, world.address, world.port);
+		return send_transfer(world, conn);
+	}
+
+	if let Err(e) = update(characters.find(chara.id))
+	.set((world_zone.eq(state.zone_id() as i32), world_instance.eq(0), world_clone.eq(0), last_login.eq(unix_now())))
+	.execute(state.db()) {
+		return Err(Error::new(Other, format!("Error saving character location: {}", e)));
+	}
+
 	let lsz = LoadStaticZone {
-		zone_id: ZoneId { map_id: 1100, instance_id: 0, clone_id: 0 },
-		map_checksum: 0x49525511,
-		player_position: Vector3::ZERO,
+		zone_id: ZoneId { map_id: state.zone_id(), instance_id: 0, clone_id: 0 },
+		map_checksum: state.zone_checksum(),
+		player_position,
 		instance_type: InstanceType::Public,
 	};
 
 	conn.send(lsz)
 }
 
-pub fn on_char_del_req(state: &State, msg: &CharacterDeleteRequest, acc_info: &AccountInfo, conn: &mut Connection) -> Res<()> {
-	use crate::schema::characters::dsl::{characters, id, username};
+pub fn on_char_rename_req(state: &State, msg: &CharacterRenameRequest, acc_info: &AccountInfo, conn: &mut Connection) -> Res<()> {
+	use crate::schema::characters::dsl::{characters, id, username, pending_name, name_approved, deleted_at};
+
+	let new_name = String::from(&msg.new_name);
+	if !is_valid_name(&new_name) {
+		return conn.send(CharacterRenameResponse::NameUnavailable);
+	}
+	if is_name_taken(state, &new_name) {
+		return conn.send(CharacterRenameResponse::NameInUse);
+	}
 
-	let success = delete(characters
+	// the new name goes through moderation like a custom name at creation
+	let updated = update(characters
 	.filter(username.eq(&acc_info.username()))
-	.filter(id.eq(msg.char_id as i32)))
-	.execute(state.db()).is_ok();
+	.filter(id.eq((msg.char_id & !BITS_PERSISTENT) as i32))
+	.filter(deleted_at.is_null()))
+	.set((pending_name.eq(Some(&new_name)), name_approved.eq(false)))
+	.execute(state.db());
 
-	if !success {
-		eprintln!("Error deleting character {} from user {}", msg.char_id, acc_info.username());
+	match updated {
+		Ok(1) => {},
+		Ok(_) => {
+			println!("{} tried to rename character {} which is not theirs!", acc_info.username(), msg.char_id);
+			return conn.send(CharacterRenameResponse::GeneralFailure);
+		}
+		Err(e) => {
+			conn.send(CharacterRenameResponse::GeneralFailure)?;
+			return Err(Error::new(Other, format!("Error renaming character: {}", e)));
+		}
 	}
 
+	conn.send(CharacterRenameResponse::Success)?;
+	on_char_list_req(state, acc_info, conn)
+}
+
+/// Saves the character as being in the destination zone and sends the client to the world server serving it.
+pub fn transfer_to_zone(state: &State, char_id: i32, zone_id: u16, conn: &mut Connection) -> Res<()> {
+	use crate::schema::characters::dsl::{characters, world_zone, world_instance, world_clone, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w};
+
+	let world = match state.world_for_zone(zone_id) {
+		Some(x) => x,
+		None => {
+			eprintln!("No world server configured for zone {}!", zone_id);
+			return Ok(());
+		}
+	};
+
+	if let Err(e) = update(characters.find(char_id))
+	.set((
+		world_zone.eq(zone_id as i32), world_instance.eq(0), world_clone.eq(0),
+		// saved position is only valid in the old zone
+		pos_x.eq(None::<f32>), pos_y.eq(None::<f32>), pos_z.eq(None::<f32>),
+		rot_x.eq(None::<f32>), rot_y.eq(None::<f32>), rot_z.eq(None::<f32>), rot_w.eq(None::<f32>),
+	))
+	.execute(state.db()) {
+		return Err(Error::new(Other, format!("Error saving character location: {}", e)));
+	}
+
+	println!("Transferring character {} to zone {} at {}:{}", char_id, zone_id, world.address, world.port);
+	send_transfer(world, conn)
+}
+
+fn send_transfer(world: &WorldConf, conn: &mut Connection) -> Res<()> {
+	conn.send(TransferToWorld {
+		redirect_ip: world.address.as_bytes().try_into().unwrap(),
+		redirect_port: world.port,
+		is_maintenance_transfer: false,
+	})
+}
+
+pub fn on_char_del_req(state: &State, msg: &CharacterDeleteRequest, acc_info: &AccountInfo, conn: &mut Connection) -> Res<()> {
+	use crate::schema::characters::dsl::{characters, id, username, deleted_at};
+
+	let char_id = (msg.char_id & !BITS_PERSISTENT) as i32;
+	let chara: Option<Character> = characters
+	.filter(username.eq(&acc_info.username()))
+	.filter(id.eq(char_id))
+	.filter(deleted_at.is_null())
+	.first(state.db()).optional().expect("Error loading character");
+	let chara = match chara {
+		Some(x) => x,
+		None => {
+			println!("{} tried to delete character {} which is not theirs!", acc_info.username(), msg.char_id);
+			return conn.send(CharacterDeleteResponse { success: false });
+		}
+	};
+	// the DB knows about characters logged in on other instances
+	if chara.is_online || acc_info.selected_char_id() == Some(char_id) || state.is_char_online(char_id) {
+		println!("{} tried to delete character {} while it is logged in", acc_info.username(), msg.char_id);
+		return conn.send(CharacterDeleteResponse { success: false });
+	}
+
+	let success = if state.character_conf().delete_grace_days == 0 {
+		match purge_char(state, char_id) {
+			Ok(()) => true,
+			Err(e) => { eprintln!("{}", e); false }
+		}
+	} else {
+		// keep the character around so a moderator can restore it until the grace period is over
+		match update(characters.find(char_id)).set(deleted_at.eq(unix_now())).execute(state.db()) {
+			Ok(_) => true,
+			Err(e) => { eprintln!("Error deleting character {}: {}", char_id, e); false }
+		}
+	};
+
 	conn.send(CharacterDeleteResponse { success })
 }
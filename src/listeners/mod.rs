@@ -1,20 +1,25 @@
 //! Message listeners responsible for the behavior of the server in response to incoming messages.
 mod chara;
 
+use std::convert::TryInto;
 use std::io::{Result as Res};
 
 use lu_packets::{
 	lu, lnv,
+	common::LuStrExt,
 	chat::client::GeneralChatMessage as ClientChatMessage,
 	world::client::{ChatModerationString, CreateCharacter},
 	world::gm::client::GameMessage as ClientGM,
-	world::gm::server::{SubjectGameMessage as ServerSGM},
+	world::gm::server::{GameMessage as ServerGM, ParseChatMessage, SubjectGameMessage as ServerSGM},
 	world::server::{GeneralChatMessage as ServerChatMessage, LevelLoadComplete, PositionUpdate, StringCheck, WorldMessage},
 };
 
+use crate::commands::on_chat_command;
+use crate::services::GetPosition;
 use crate::state::{AccountInfo, Connection, State};
 
-use self::chara::{on_char_create_req, on_char_del_req, on_char_list_req, on_char_login_req};
+use self::chara::{on_char_create_req, on_char_del_req, on_char_list_req, on_char_login_req, on_char_rename_req};
+pub use self::chara::{restore_cutoff, transfer_to_zone, MAX_CHARACTERS};
 
 pub fn on_validated_msg(state: &mut State, msg: &WorldMessage, acc_info: &mut AccountInfo, conn: &mut Connection) -> Res<()> {
 	use lu_packets::world::server::WorldMessage::*;
@@ -23,8 +28,9 @@ pub fn on_validated_msg(state: &mut State, msg: &WorldMessage, acc_info: &mut Ac
 		CharacterCreateRequest(msg) => on_char_create_req(state, msg, acc_info, conn),
 		CharacterLoginRequest(msg)  => on_char_login_req(state, msg, acc_info, conn),
 		CharacterDeleteRequest(msg) => on_char_del_req(state, msg, acc_info, conn),
+		CharacterRenameRequest(msg) => on_char_rename_req(state, msg, acc_info, conn),
 		GeneralChatMessage(msg)     => on_general_chat_msg(state, msg, acc_info, conn),
-		SubjectGameMessage(msg)     => on_subject_game_msg(state, msg, conn),
+		SubjectGameMessage(msg)     => on_subject_game_msg(state, msg, acc_info, conn),
 		LevelLoadComplete(msg)      => on_level_load_complete(state, msg, acc_info, conn),
 		PositionUpdate(msg)         => on_position_update(state, msg, acc_info, conn),
 		StringCheck(msg)            => on_string_check(state, msg, conn),
@@ -37,7 +43,7 @@ fn on_general_chat_msg(state: &mut State, msg: &ServerChatMessage, acc_info: &Ac
 		conn.broadcast(ClientChatMessage {
 			chat_channel: msg.chat_channel,
 			sender: sender.object_id(),
-			sender_name: lu!(""),
+			sender_name: (&*sender.name().to_string()).try_into().unwrap(),
 			source_id: msg.source_id,
 			sender_gm_level: 0,
 			message: msg.message.clone().into(),
@@ -46,27 +52,36 @@ fn on_general_chat_msg(state: &mut State, msg: &ServerChatMessage, acc_info: &Ac
 }
 
 fn on_level_load_complete(state: &mut State, _msg: &LevelLoadComplete, acc_info: &mut AccountInfo, conn: &mut Connection) -> Res<()> {
-	let chara = state.spawn_player(acc_info).unwrap();
+	if acc_info.selected_char_id().is_none() {
+		println!("{} finished loading without selecting a character!", acc_info.username());
+		return Ok(());
+	}
+	if acc_info.active_character_id() != 0 {
+		println!("{} finished loading again while already playing {}!", acc_info.username(), acc_info.active_character_id());
+		return Ok(());
+	}
+	let chara = state.spawn_player(conn.peer_addr().unwrap(), acc_info)?;
 
 	let mut xml = String::new();
 	chara.write_xml(&mut xml).unwrap();
 
 
 	let obj_id = chara.object_id();
-	let name = &format!("{}", obj_id)[..];
+	let name = chara.name().to_string();
 
 	let chardata = CreateCharacter { data: lnv! {
 		"objid": obj_id,
 		"template": 1i32,
-		"name": name,
+		"name": &name[..],
 		"xmlData": &xml[..],
 	}};
 	conn.send(chardata)?;
 
-	for game_object in state.all_game_objects() {
-		let replica = game_object.make_construction();
-		conn.broadcast(replica)?;
-	}
+	// the client gets everything around it, everyone else only the new player
+	let mut get_pos = GetPosition::default();
+	state.game_object(obj_id).unwrap().run_service(&mut get_pos);
+	state.update_ghosts_for(conn.peer_addr().unwrap(), get_pos.0, conn)?;
+	state.construct(obj_id, conn)?;
 
 	state.with_game_object(obj_id, |_state, chara| {
 		let serverdone = chara.make_sgm(ClientGM::ServerDoneLoadingAllObjects);
@@ -80,18 +95,64 @@ fn on_level_load_complete(state: &mut State, _msg: &LevelLoadComplete, acc_info:
 	})
 }
 
-fn on_subject_game_msg(state: &mut State, msg: &ServerSGM, conn: &mut Connection) -> Res<()> {
+/// Whether the message can only be about the sender's own character.
+fn is_own_char_only(msg: &ServerGM) -> bool {
+	matches!(msg,
+		ServerGM::EquipInventory(_)
+		| ServerGM::UnEquipInventory(_)
+		| ServerGM::MoveItemInInventory(_)
+		| ServerGM::MoveItemBetweenInventoryTypes(_)
+		| ServerGM::RemoveItemFromInventory(_)
+		| ServerGM::UseNonEquipmentItem(_)
+		| ServerGM::RequestDie(_)
+		| ServerGM::RequestResurrect { .. }
+		| ServerGM::PickupItem(_)
+		| ServerGM::RequestUse(_)
+		| ServerGM::ParseChatMessage(_)
+	)
+}
+
+fn on_subject_game_msg(state: &mut State, msg: &ServerSGM, acc_info: &AccountInfo, conn: &mut Connection) -> Res<()> {
+	// nobody gets to act for another player
+	if msg.subject_id != acc_info.active_character_id() && (is_own_char_only(&msg.message) || state.is_player(msg.subject_id)) {
+		println!("{} sent a message for {} which is not their character!", acc_info.username(), msg.subject_id);
+		return Ok(());
+	}
+	// commands need to know who is sending them
+	if let ServerGM::ParseChatMessage(x) = &msg.message {
+		return on_parse_chat_message(state, x, acc_info, conn);
+	}
 	state.with_game_object(msg.subject_id, |state, game_object| {
 		game_object.on_game_message(&msg.message, state, conn)
 	})
 }
 
-fn on_position_update(state: &mut State, msg: &PositionUpdate, acc_info: &AccountInfo, conn: &mut Connection) -> Res<()> {
-	state.with_char(acc_info, |state, game_object| {
-		game_object.run_service_mut(&msg.frame_stats, state, conn)
+fn on_parse_chat_message(state: &mut State, msg: &ParseChatMessage, acc_info: &AccountInfo, conn: &mut Connection) -> Res<()> {
+	let string = msg.string.to_string();
+	if !string.starts_with("/") {
+		return Ok(());
+	}
+	dbg!(msg);
+	state.with_char(acc_info, |state, sender| {
+		on_chat_command(state, &string, acc_info, sender, conn);
+		Ok(())
 	})
 }
 
+fn on_position_update(state: &mut State, msg: &PositionUpdate, acc_info: &mut AccountInfo, conn: &mut Connection) -> Res<()> {
+	state.with_char(acc_info, |state, game_object| {
+		game_object.run_service_mut(&msg.frame_stats, state, conn)?;
+		// others might have come into or gone out of range
+		let addr = conn.peer_addr().unwrap();
+		state.update_ghosts_for(addr, msg.frame_stats.position, conn)?;
+		state.update_ghost(game_object, conn)
+	})?;
+	if acc_info.position_save_due() {
+		state.save_char_position(acc_info)?;
+	}
+	Ok(())
+}
+
 fn on_string_check(_state: &State, msg: &StringCheck, conn: &mut Connection) -> Res<()> {
 	let resp = ChatModerationString {
 		request_id: msg.request_id,
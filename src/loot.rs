@@ -0,0 +1,82 @@
+//! Loot drops, rolled from the cdclient loot tables.
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use rusqlite::{Connection as RusqliteConnection, params};
+
+use lu_packets::world::Lot;
+
+/// Xorshift random number generator, plenty for loot rolls.
+struct Rng(u64);
+
+impl Rng {
+	/// Seeds the generator from the random keys the standard library uses for hash maps.
+	fn new() -> Self {
+		Self(RandomState::new().build_hasher().finish() | 1)
+	}
+
+	fn next_u64(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	/// Returns a number in [0, 1).
+	fn next_f64(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+
+	/// Returns a number in [0, n), n has to be positive.
+	fn below(&mut self, n: usize) -> usize {
+		(self.next_u64() % n as u64) as usize
+	}
+}
+
+/// Rolls the items dropped by an object with the given `LootMatrixIndex`.
+///
+/// Each `LootMatrix` entry drops with its percent chance, and then drops between its min and max number of items. The rarity of each item is rolled on the entry's `RarityTable`, and the item is picked from the entry's `LootTable` items of that rarity. Mission drops are left out, since there are no missions yet.
+pub fn roll_loot(cdclient: &RusqliteConnection, loot_matrix_index: i32) -> Vec<Lot> {
+	let mut rng = Rng::new();
+	let mut stmt = cdclient.prepare("select LootTableIndex, RarityTableIndex, percent, minToDrop, maxToDrop from lootmatrix where LootMatrixIndex = ?").unwrap();
+	let entries: Vec<(i32, i32, f64, i32, i32)> = match stmt.query_map(params![loot_matrix_index], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))) {
+		Ok(rows) => rows.filter_map(Result::ok).collect(),
+		Err(_) => return vec![],
+	};
+
+	let mut lots = vec![];
+	for (loot_table_index, rarity_table_index, percent, min_to_drop, max_to_drop) in entries {
+		if rng.next_f64() >= percent {
+			continue;
+		}
+		let count = if max_to_drop > min_to_drop { min_to_drop + rng.below((max_to_drop - min_to_drop + 1) as usize) as i32 } else { min_to_drop };
+		for _ in 0..count {
+			let rarity = roll_rarity(cdclient, rarity_table_index, rng.next_f64());
+			if let Some(lot) = pick_item(cdclient, loot_table_index, rarity, &mut rng) {
+				lots.push(lot);
+			}
+		}
+	}
+	lots
+}
+
+/// Looks up the rarity a roll between 0 and 1 falls into, `None` if the table doesn't cover it.
+fn roll_rarity(cdclient: &RusqliteConnection, rarity_table_index: i32, roll: f64) -> Option<i32> {
+	let mut stmt = cdclient.prepare("select rarity from raritytable where RarityTableIndex = ? and randmax >= ? order by randmax limit 1").unwrap();
+	stmt.query_row(params![rarity_table_index, roll], |row| row.get(0)).ok()
+}
+
+/// Picks a random item of the rarity from the loot table, or of any rarity if there is none of it.
+fn pick_item(cdclient: &RusqliteConnection, loot_table_index: i32, rarity: Option<i32>, rng: &mut Rng) -> Option<Lot> {
+	let mut stmt = cdclient.prepare("select lt.itemid, ic.rarity from loottable lt join componentsregistry cr on cr.id = lt.itemid and cr.component_type = 11 join itemcomponent ic on ic.id = cr.component_id where lt.LootTableIndex = ? and lt.MissionDrop = 0").unwrap();
+	let items: Vec<(Lot, Option<i32>)> = match stmt.query_map(params![loot_table_index], |row| Ok((row.get(0)?, row.get(1)?))) {
+		Ok(rows) => rows.filter_map(Result::ok).collect(),
+		Err(_) => return None,
+	};
+	let of_rarity: Vec<Lot> = items.iter().filter(|(_, x)| rarity.is_some() && *x == rarity).map(|(lot, _)| *lot).collect();
+	let candidates = if !of_rarity.is_empty() { of_rarity } else { items.iter().map(|(lot, _)| *lot).collect() };
+	if candidates.is_empty() {
+		return None;
+	}
+	Some(candidates[rng.below(candidates.len())])
+}
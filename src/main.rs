@@ -5,15 +5,20 @@ extern crate diesel;
 mod commands;
 mod game_object;
 mod listeners;
+mod loot;
 mod models;
+mod names;
 mod schema;
 mod services;
+mod spawner;
 mod state;
+mod zone;
 
 use serde::Deserialize;
 
 use base_server::{create_tls_config, TlsConf, server::Server};
 use lu_packets::{
+	world::Lot,
 	world::client::Message as OutMessage,
 	world::server::Message as IncMessage,
 };
@@ -25,6 +30,20 @@ struct Config {
 	db: DbConf,
 	cdclient: CdclientConf,
 	tls: TlsConf,
+	zone: ZoneConf,
+	names: NamesConf,
+	#[serde(default)]
+	inventory: InventoryConf,
+	#[serde(default)]
+	characters: CharacterConf,
+	#[serde(default)]
+	ghosting: GhostingConf,
+	/// Other world servers characters can be sent to.
+	#[serde(default)]
+	worlds: Vec<WorldConf>,
+	/// Usernames of accounts allowed to use moderation commands.
+	#[serde(default)]
+	moderators: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -37,6 +56,78 @@ struct CdclientConf {
 	path: String,
 }
 
+#[derive(Deserialize)]
+struct ZoneConf {
+	/// Zone ID of the world served by this instance.
+	id: u16,
+	/// Path to the client's res/maps directory.
+	maps_path: String,
+}
+
+#[derive(Deserialize)]
+struct NamesConf {
+	/// Path to the client's res/names directory.
+	path: String,
+}
+
+/// Address of the world server serving a zone.
+#[derive(Deserialize)]
+pub struct WorldConf {
+	pub zone_id: u16,
+	pub address: String,
+	pub port: u16,
+}
+
+/// Sizes of the inventory bags, types not listed here are unlimited.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct InventoryConf {
+	pub items: u32,
+	pub vault_items: u32,
+	pub bricks: u32,
+	pub models: u32,
+	pub vault_models: u32,
+}
+
+impl Default for InventoryConf {
+	fn default() -> Self {
+		Self {
+			items: 20,
+			vault_items: 40,
+			bricks: 200,
+			models: 40,
+			vault_models: 40,
+		}
+	}
+}
+
+/// Character management settings.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct CharacterConf {
+	/// Days a deleted character can still be restored by a moderator, 0 to delete immediately.
+	pub delete_grace_days: u32,
+}
+
+/// Which objects are constructed for a client, based on the distance to its player.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct GhostingConf {
+	/// Distance up to which objects are visible, 0 to make everything visible.
+	pub radius: f32,
+	/// LOTs of objects that are visible at any distance.
+	pub always_visible: Vec<Lot>,
+}
+
+impl Default for GhostingConf {
+	fn default() -> Self {
+		Self {
+			radius: 250.0,
+			always_visible: vec![],
+		}
+	}
+}
+
 fn load_config() -> Config {
 	let mut exe_path = std::env::current_exe().expect("program location unknown");
 	exe_path.pop();
@@ -51,7 +142,10 @@ fn load_config() -> Config {
 fn main() {
 	let config = load_config();
 	let tls_config = create_tls_config(config.tls);
-	let mut state = State::new(&config.cdclient.path, &config.db.path);
+	let mut state = State::new(&config.cdclient.path, &config.db.path, config.inventory, config.characters, config.ghosting, config.worlds, config.moderators);
+	state.load_zone(&config.zone.maps_path, config.zone.id).expect("cannot load zone");
+	state.reset_online_chars().expect("cannot reset online characters");
+	state.load_predef_names(&config.names.path).expect("cannot load predefined names");
 	let mut server = Server::<IncMessage, OutMessage, _>::new("0.0.0.0:10000", tls_config, |i, o| state.on_msg(i, o)).unwrap();
 	println!("Started up");
 	server.run();
@@ -1,13 +1,13 @@
 //! Database models.
 use diesel::{Insertable, Queryable};
 
-use crate::schema::characters;
+use crate::schema::{characters, inventory_items};
 
 #[derive(Debug)]
 #[derive(Queryable)]
 #[derive(Insertable)]
 pub struct Character {
-	/// Object ID.
+	/// Object ID, without the persistent flag.
 	pub id: i32,
 	/// Name of the account this character belongs to.
 	pub username: String,
@@ -33,4 +33,43 @@ pub struct Character {
 	pub world_instance: i32,
 	/// Clone ID of the world where the character is.
 	pub world_clone: i32,
+	/// Saved position in the world, `None` to use the zone's spawn point.
+	pub pos_x: Option<f32>,
+	pub pos_y: Option<f32>,
+	pub pos_z: Option<f32>,
+	/// Saved rotation in the world.
+	pub rot_x: Option<f32>,
+	pub rot_y: Option<f32>,
+	pub rot_z: Option<f32>,
+	pub rot_w: Option<f32>,
+	/// Custom name awaiting moderator approval.
+	pub pending_name: Option<String>,
+	/// Whether the last custom name was approved, the character has to be renamed if not and none is pending.
+	pub name_approved: bool,
+	/// Unix timestamp of the last time the character was logged in.
+	pub last_login: i64,
+	/// Unix timestamp of when the character was deleted, it can be restored until the grace period is over.
+	pub deleted_at: Option<i64>,
+	/// Whether the character is in a world on any instance.
+	pub is_online: bool,
+}
+
+#[derive(Debug)]
+#[derive(Queryable)]
+#[derive(Insertable)]
+pub struct InventoryItem {
+	/// Object ID, without the persistent flag.
+	pub id: i64,
+	/// ID of the character owning this item.
+	pub character_id: i32,
+	/// LOT of the item.
+	pub lot: i32,
+	/// Stack size.
+	pub count: i32,
+	/// Slot in the inventory.
+	pub slot: i32,
+	/// Inventory type the item is in.
+	pub inventory_type: i32,
+	/// Whether the item is currently equipped.
+	pub is_equipped: bool,
 }
@@ -0,0 +1,38 @@
+//! The client's lists of predefined three-part character names.
+use std::io::Result as Res;
+use std::path::Path;
+
+pub struct PredefNames {
+	first: Vec<String>,
+	middle: Vec<String>,
+	last: Vec<String>,
+}
+
+impl PredefNames {
+	pub fn new() -> Self {
+		Self { first: vec![], middle: vec![], last: vec![] }
+	}
+
+	/// Loads the name lists from the client's res/names directory.
+	pub fn load(names_path: &str) -> Res<Self> {
+		let path = Path::new(names_path);
+		Ok(Self {
+			first: Self::load_list(&path.join("minifigname_first.txt"))?,
+			middle: Self::load_list(&path.join("minifigname_middle.txt"))?,
+			last: Self::load_list(&path.join("minifigname_last.txt"))?,
+		})
+	}
+
+	fn load_list(path: &Path) -> Res<Vec<String>> {
+		let list = std::fs::read_to_string(path)?;
+		Ok(list.lines().map(str::trim).filter(|x| !x.is_empty()).map(String::from).collect())
+	}
+
+	/// Puts together the name for the given list indices, `None` if any index is out of range.
+	pub fn get(&self, first: u32, middle: u32, last: u32) -> Option<String> {
+		let first = self.first.get(first as usize)?;
+		let middle = self.middle.get(middle as usize)?;
+		let last = self.last.get(last as usize)?;
+		Some(format!("{}{}{}", first, middle, last))
+	}
+}
@@ -13,5 +13,44 @@ table! {
         world_zone -> Integer,
         world_instance -> Integer,
         world_clone -> Integer,
+        pos_x -> Nullable<Float>,
+        pos_y -> Nullable<Float>,
+        pos_z -> Nullable<Float>,
+        rot_x -> Nullable<Float>,
+        rot_y -> Nullable<Float>,
+        rot_z -> Nullable<Float>,
+        rot_w -> Nullable<Float>,
+        pending_name -> Nullable<Text>,
+        name_approved -> Bool,
+        last_login -> BigInt,
+        deleted_at -> Nullable<BigInt>,
+        is_online -> Bool,
     }
 }
+
+table! {
+    counters (name) {
+        name -> Text,
+        value -> BigInt,
+    }
+}
+
+table! {
+    inventory_items (id) {
+        id -> BigInt,
+        character_id -> Integer,
+        lot -> Integer,
+        count -> Integer,
+        slot -> Integer,
+        inventory_type -> Integer,
+        is_equipped -> Bool,
+    }
+}
+
+joinable!(inventory_items -> characters (character_id));
+
+allow_tables_to_appear_in_same_query!(
+    characters,
+    counters,
+    inventory_items,
+);
@@ -1,3 +1,4 @@
+use lu_packets::common::ObjId;
 use lu_packets::world::{Lot, Quaternion, Vector3};
 use lu_packets::raknet::client::replica::controllable_physics::FrameStats;
 use lu_packets_derive::FromVariants;
@@ -6,6 +7,7 @@ use lu_packets_derive::FromVariants;
 pub enum GameObjectService<'a> {
 	GetPosition(&'a mut GetPosition),
 	GetRotation(&'a mut GetRotation),
+	IsDead(&'a mut IsDead),
 }
 
 #[derive(Debug, Default)]
@@ -14,17 +16,54 @@ pub struct GetPosition(pub Vector3);
 #[derive(Debug, Default)]
 pub struct GetRotation(pub Quaternion);
 
+#[derive(Debug, Default)]
+pub struct IsDead(pub bool);
+
 #[derive(FromVariants)]
 #[non_exhaustive]
 pub enum GameObjectServiceMut<'a> {
 	SetFrameStats(&'a FrameStats),
-	AddItem(&'a AddItem),
+	AddItem(&'a mut AddItem),
+	RemoveItem(&'a mut RemoveItem),
 	SetFaction(&'a SetFaction),
+	Damage(&'a mut Damage),
+	Heal(&'a Heal),
+	RepairArmor(&'a RepairArmor),
+	ChangeImagination(&'a ChangeImagination),
 }
 
 pub struct AddItem {
 	pub lot: Lot,
+	pub count: u32,
+	/// Set to how many items actually fit in the inventory.
+	pub added: u32,
+}
+
+/// Removes items by object ID, or from any stacks of the LOT if the object ID is 0.
+pub struct RemoveItem {
+	pub object_id: ObjId,
+	pub lot: Lot,
+	pub count: u32,
+	/// Set to how many items were actually removed.
+	pub removed: u32,
 }
 
 pub struct SetFaction(pub i32);
 
+
+pub struct Damage {
+	pub amount: u32,
+	/// Object the damage comes from.
+	pub source: ObjId,
+	/// Set to how much damage was taken by armor and health.
+	pub dealt: u32,
+	/// Set if the damage killed the object.
+	pub killed: bool,
+}
+
+pub struct Heal(pub u32);
+
+pub struct RepairArmor(pub u32);
+
+/// Adds imagination, or removes it if negative.
+pub struct ChangeImagination(pub i32);
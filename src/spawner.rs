@@ -0,0 +1,140 @@
+//! Spawners, which keep a number of objects of a LOT alive at their nodes.
+use rusqlite::{Connection as RusqliteConnection, params};
+
+use lu_packets::{
+	lu,
+	common::ObjId,
+	world::{Lot, LuNameValue, LnvValue, Quaternion, Vector3},
+};
+
+/// A place where a spawner can spawn its object.
+pub struct SpawnerNode {
+	pub position: Vector3,
+	pub rotation: Quaternion,
+}
+
+pub struct Spawner {
+	/// Object ID from the level file, reported to clients as the spawned objects' spawner ID.
+	id: ObjId,
+	lot: Lot,
+	nodes: Vec<SpawnerNode>,
+	/// Seconds until a destroyed object is respawned.
+	respawn_time: f32,
+	number_to_maintain: usize,
+	/// Config passed on to the spawned objects.
+	config: LuNameValue,
+	/// Spawned objects, with the index of the node they were spawned at.
+	children: Vec<(ObjId, usize)>,
+	/// Remaining seconds of each pending respawn.
+	respawns: Vec<f32>,
+	/// Node to try first for the next spawn, so spawns rotate through the nodes.
+	next_node: usize,
+}
+
+impl Spawner {
+	/// Checks whether a level object is a spawner, by its config or by the LOT having a spawner component.
+	pub fn is_spawner(lot: Lot, config: &LuNameValue, cdclient: &RusqliteConnection) -> bool {
+		if config.get(&lu!("spawntemplate")).is_some() {
+			return true;
+		}
+		let mut stmt = cdclient.prepare("select count(*) from componentsregistry where id = ? and component_type = 10").unwrap();
+		let count: i64 = stmt.query_row(params![lot], |row| row.get(0)).unwrap_or(0);
+		count > 0
+	}
+
+	/// Creates a spawner from its level config, `None` if it has no template to spawn.
+	pub fn new(id: ObjId, nodes: Vec<SpawnerNode>, config: &LuNameValue) -> Option<Self> {
+		let lot = match config.get(&lu!("spawntemplate")) {
+			Some(LnvValue::I32(x)) => *x,
+			Some(LnvValue::U32(x)) => *x as Lot,
+			_ => return None,
+		};
+		let respawn_time = match config.get(&lu!("respawn")) {
+			Some(LnvValue::F32(x)) => *x,
+			Some(LnvValue::I32(x)) => *x as f32,
+			_ => 10.0,
+		};
+		let number_to_maintain = match config.get(&lu!("number_to_maintain")) {
+			Some(LnvValue::I32(x)) if *x > 0 => *x as usize,
+			Some(LnvValue::U32(x)) if *x > 0 => *x as usize,
+			_ => 1,
+		};
+		let active = match config.get(&lu!("spawner_active_on_load")) {
+			Some(LnvValue::Bool(x)) => *x,
+			_ => true,
+		};
+		let number_to_maintain = number_to_maintain.min(nodes.len());
+		// the initial spawns are due right away
+		let respawns = if active { vec![0.0; number_to_maintain] } else { vec![] };
+
+		Some(Self {
+			id,
+			lot,
+			nodes,
+			respawn_time,
+			number_to_maintain,
+			config: config.clone(),
+			children: vec![],
+			respawns,
+			next_node: 0,
+		})
+	}
+
+	pub fn id(&self) -> ObjId {
+		self.id
+	}
+
+	pub fn lot(&self) -> Lot {
+		self.lot
+	}
+
+	/// Advances the respawn timers, returning how many spawns are due.
+	pub fn update(&mut self, dt: f32) -> usize {
+		for remaining in &mut self.respawns {
+			*remaining -= dt;
+		}
+		let before = self.respawns.len();
+		self.respawns.retain(|x| *x > 0.0);
+		before - self.respawns.len()
+	}
+
+	/// Picks a free node for the next spawn and puts together the spawned object's config, `None` if all nodes are taken.
+	pub fn next_spawn(&mut self) -> Option<(usize, LuNameValue)> {
+		if self.children.len() >= self.number_to_maintain {
+			return None;
+		}
+		let node_count = self.nodes.len();
+		let node_index = (0..node_count)
+			.map(|i| (self.next_node + i) % node_count)
+			.find(|i| !self.children.iter().any(|(_, x)| x == i))?;
+		self.next_node = (node_index + 1) % node_count;
+
+		let node = &self.nodes[node_index];
+		let mut config = self.config.clone();
+		config.insert(lu!("position_x"), LnvValue::F32(node.position.x));
+		config.insert(lu!("position_y"), LnvValue::F32(node.position.y));
+		config.insert(lu!("position_z"), LnvValue::F32(node.position.z));
+		config.insert(lu!("rotation_x"), LnvValue::F32(node.rotation.x));
+		config.insert(lu!("rotation_y"), LnvValue::F32(node.rotation.y));
+		config.insert(lu!("rotation_z"), LnvValue::F32(node.rotation.z));
+		config.insert(lu!("rotation_w"), LnvValue::F32(node.rotation.w));
+		config.insert(lu!("spawner_id"), LnvValue::I64(self.id as i64));
+		config.insert(lu!("spawner_node_id"), LnvValue::U32(node_index as u32));
+		Some((node_index, config))
+	}
+
+	pub fn add_child(&mut self, obj_id: ObjId, node_index: usize) {
+		self.children.push((obj_id, node_index));
+	}
+
+	/// Frees the node of a destroyed object and schedules its respawn, returns whether the object was spawned by this spawner.
+	pub fn on_child_destroyed(&mut self, obj_id: ObjId) -> bool {
+		let index = match self.children.iter().position(|(x, _)| *x == obj_id) {
+			Some(x) => x,
+			None => return false,
+		};
+		self.children.remove(index);
+		self.respawns.push(self.respawn_time);
+		true
+	}
+}
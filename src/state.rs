@@ -1,89 +1,355 @@
 //! State providing context for listeners.
-use std::collections::{hash_map, HashMap};
-use std::io::Result as Res;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind::{NotFound, Other}, Result as Res};
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
-use diesel::prelude::SqliteConnection;
+use diesel::prelude::*;
+use diesel::dsl::update;
 use rusqlite::Connection as RusqliteConnection;
 
 use lu_packets::{
-	lnv,
+	lnv, lu,
 	common::ObjId,
 	general::client::DisconnectNotify,
-	world::{Lot, LuNameValue},
+	world::{Lot, LuNameValue, LnvValue, Quaternion, Vector3},
 	world::client::Message as OutMessage,
+	world::gm::client::{DropClientLoot, SubjectGameMessage as ClientSGM},
 	world::server::{ClientValidation, Message as IncMessage, WorldMessage},
 };
 use lu_packets::common::ServiceId;
 use base_server::listeners::{on_conn_req, on_internal_ping, on_handshake};
 use base_server::server::Context as C;
 
+use crate::{CharacterConf, GhostingConf, InventoryConf, WorldConf};
 use crate::game_object::GameObject;
 use crate::listeners::on_validated_msg;
+use crate::models::Character;
+use crate::names::PredefNames;
+use crate::services::{GetPosition, GetRotation};
+use crate::spawner::{Spawner, SpawnerNode};
+use crate::zone::{known_checksum, Zone};
 pub type Connection = C<IncMessage, OutMessage>;
 
+pub const BITS_PERSISTENT: ObjId = 1 << 60;
+const BITS_LOCAL: ObjId = 1 << 46;
+const BITS_SPAWNED: ObjId = 1 << 58 | BITS_LOCAL;
+
+/// How often a character's position is saved while moving.
+const POSITION_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Minimum time between updates of the game objects.
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
 pub struct AccountInfo {
 	username: String,
+	/// DB ID of the character selected at login, once it has been verified to belong to this account.
+	selected_char_id: Option<i32>,
 	active_character_id: ObjId,
+	last_position_save: Instant,
 }
 
 impl AccountInfo {
 	pub fn username(&self) -> &String {
 		&self.username
 	}
+
+	/// Object ID of the player object, 0 until it has been spawned.
+	pub fn active_character_id(&self) -> ObjId {
+		self.active_character_id
+	}
+
+	pub fn select_char(&mut self, char_id: i32) {
+		self.selected_char_id = Some(char_id);
+	}
+
+	pub fn selected_char_id(&self) -> Option<i32> {
+		self.selected_char_id
+	}
+
+	pub fn position_save_due(&self) -> bool {
+		self.last_position_save.elapsed() >= POSITION_SAVE_INTERVAL
+	}
+}
+
+/// Messages for clients other than the one whose message is being handled.
+///
+/// The connection context can only send to its own client or broadcast to all, so messages for other clients wait here until their client's next message. Clients ping regularly, so they don't wait for long.
+#[derive(Default)]
+struct Outbox(HashMap<SocketAddr, Vec<OutMessage>>);
+
+impl Outbox {
+	/// Sends the message right away if it's for the current client, otherwise queues it.
+	fn send<T: Into<OutMessage>>(&mut self, addr: SocketAddr, msg: T, conn: &mut Connection) -> Res<()> {
+		if conn.peer_addr().unwrap() == addr {
+			return conn.send(msg);
+		}
+		self.0.entry(addr).or_insert_with(Vec::new).push(msg.into());
+		Ok(())
+	}
+
+	/// Sends the messages queued for the current client.
+	fn flush(&mut self, conn: &mut Connection) -> Res<()> {
+		if let Some(msgs) = self.0.remove(&conn.peer_addr().unwrap()) {
+			for msg in msgs {
+				conn.send(msg)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// What a client in the world knows about, for ghosting.
+struct ReplicaClient {
+	/// Position of the client's player as of the last ghosting update.
+	position: Vector3,
+	/// Network IDs of the replicas the client has constructed.
+	known: HashSet<u16>,
+}
+
+impl ReplicaClient {
+	/// Constructs or destroys the object's replica, depending on whether the client should see it.
+	fn update_ghost(&mut self, addr: SocketAddr, game_object: &GameObject, conf: &GhostingConf, outbox: &mut Outbox, conn: &mut Connection) -> Res<()> {
+		let visible = conf.radius == 0.0 || conf.always_visible.contains(&game_object.lot()) || match game_object.position() {
+			Some(pos) => {
+				let (dx, dy, dz) = (pos.x - self.position.x, pos.y - self.position.y, pos.z - self.position.z);
+				dx*dx + dy*dy + dz*dz <= conf.radius*conf.radius
+			}
+			// no idea where it is, so better always show it
+			None => true,
+		};
+		let network_id = game_object.network_id();
+		if visible && !self.known.contains(&network_id) {
+			self.known.insert(network_id);
+			outbox.send(addr, game_object.make_construction(), conn)?;
+		} else if !visible && self.known.contains(&network_id) {
+			self.known.remove(&network_id);
+			outbox.send(addr, game_object.make_destruction(), conn)?;
+		}
+		Ok(())
+	}
 }
 
 pub struct State {
 	validated: HashMap<SocketAddr, AccountInfo>,
 	game_objects: HashMap<ObjId, GameObject>,
+	spawners: Vec<Spawner>,
+	/// Objects to destroy once the current message or tick has been handled.
+	destroy_queue: Vec<ObjId>,
+	/// Replicas constructed by each client in the world.
+	replicas: HashMap<SocketAddr, ReplicaClient>,
+	outbox: Outbox,
+	/// Address of each player's client, by the player's object ID.
+	player_addrs: HashMap<ObjId, SocketAddr>,
+	/// Dropped loot that hasn't been picked up yet, with its owner and LOT.
+	loot: HashMap<ObjId, (ObjId, Lot)>,
 	current_spawned_id: ObjId,
-	current_persistent_id: ObjId,
 	current_network_id: u16,
+	/// Network IDs of destroyed objects, reused before allocating new ones.
+	free_network_ids: Vec<u16>,
+	last_tick: Instant,
+	/// Zone ID of the world served by this instance.
+	zone_id: u16,
+	zone_checksum: u32,
+	spawn_position: Vector3,
+	spawn_rotation: Quaternion,
 	cdclient: RusqliteConnection,
 	/// Connection to the users DB.
 	db: SqliteConnection,
+	inventory_conf: InventoryConf,
+	character_conf: CharacterConf,
+	ghosting_conf: GhostingConf,
+	worlds: Vec<WorldConf>,
+	moderators: Vec<String>,
+	predef_names: PredefNames,
 }
 
 impl State {
 	/// Creates a new callback connecting to the DB at the provided path.
-	pub fn new(cdclient_path: &str, db_path: &str) -> Self {
+	pub fn new(cdclient_path: &str, db_path: &str, inventory_conf: InventoryConf, character_conf: CharacterConf, ghosting_conf: GhostingConf, worlds: Vec<WorldConf>, moderators: Vec<String>) -> Self {
 		use diesel::Connection;
 
 		let cdclient = RusqliteConnection::open(cdclient_path).unwrap();
 		let db = SqliteConnection::establish(db_path).unwrap();
 
-		const BITS_PERSISTENT: ObjId = 1 << 60;
-		const BITS_LOCAL: ObjId = 1 << 46;
-		const BITS_SPAWNED: ObjId = 1 << 58 | BITS_LOCAL;
-
 		Self {
 			validated: HashMap::new(),
 			game_objects: HashMap::new(),
+			spawners: vec![],
+			destroy_queue: vec![],
+			replicas: HashMap::new(),
+			outbox: Outbox::default(),
+			player_addrs: HashMap::new(),
+			loot: HashMap::new(),
 			current_spawned_id: BITS_SPAWNED,
-			current_persistent_id: BITS_PERSISTENT,
 			current_network_id: 0,
+			free_network_ids: vec![],
+			last_tick: Instant::now(),
+			zone_id: 0,
+			zone_checksum: 0,
+			spawn_position: Vector3::ZERO,
+			spawn_rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
 			cdclient,
 			db,
+			inventory_conf,
+			character_conf,
+			ghosting_conf,
+			worlds,
+			moderators,
+			predef_names: PredefNames::new(),
+		}
+	}
+
+	/// Loads the zone's files and spawns the objects placed in its scenes.
+	pub fn load_zone(&mut self, maps_path: &str, zone_id: u16) -> Res<()> {
+		let zone = Zone::load(maps_path, zone_id, &self.cdclient)?;
+		self.zone_id = zone_id;
+		let checksum = zone.checksum();
+		// a wrong checksum keeps clients from loading the zone, so known good ones win
+		self.zone_checksum = match known_checksum(zone_id) {
+			Some(known) if known != checksum => {
+				eprintln!("Computed checksum {:#010x} of zone {} doesn't match the known {:#010x}, using the known one", checksum, zone_id, known);
+				known
+			}
+			_ => checksum,
+		};
+		self.spawn_position = zone.spawn_position;
+		self.spawn_rotation = zone.spawn_rotation;
+
+		for scene in zone.scenes {
+			let mut spawned = 0;
+			let total = scene.objects.len();
+			for mut obj in scene.objects {
+				obj.config.insert(lu!("position_x"), LnvValue::F32(obj.position.x));
+				obj.config.insert(lu!("position_y"), LnvValue::F32(obj.position.y));
+				obj.config.insert(lu!("position_z"), LnvValue::F32(obj.position.z));
+				obj.config.insert(lu!("rotation_x"), LnvValue::F32(obj.rotation.x));
+				obj.config.insert(lu!("rotation_y"), LnvValue::F32(obj.rotation.y));
+				obj.config.insert(lu!("rotation_z"), LnvValue::F32(obj.rotation.z));
+				obj.config.insert(lu!("rotation_w"), LnvValue::F32(obj.rotation.w));
+				obj.config.insert(lu!("scale"), LnvValue::F32(obj.scale));
+				if Spawner::is_spawner(obj.lot, &obj.config, &self.cdclient) {
+					// a spawner placed in a level spawns at its own position, spawners with more nodes are defined by paths
+					let nodes = vec![SpawnerNode { position: obj.position, rotation: obj.rotation }];
+					match Spawner::new(obj.id, nodes, &obj.config) {
+						Some(spawner) => { self.spawners.push(spawner); spawned += 1; }
+						None => eprintln!("Spawner {} has no template to spawn", obj.id),
+					}
+					continue;
+				}
+				match self.spawn(obj.lot, &obj.config) {
+					Ok(_) => spawned += 1,
+					Err(e) => eprintln!("Could not spawn object {} with LOT {}: {}", obj.id, obj.lot, e),
+				}
+			}
+			println!("Spawned {} of {} objects in scene {}", spawned, total, scene.id);
+		}
+		for path in zone.spawner_paths {
+			let config = lnv! {
+				"spawntemplate": path.lot,
+				"respawn": path.respawn_time as f32,
+				"number_to_maintain": path.number_to_maintain,
+				"spawner_active_on_load": path.active_on_load,
+			};
+			let nodes = path.nodes.into_iter().map(|(position, rotation)| SpawnerNode { position, rotation }).collect();
+			match Spawner::new(path.spawner_id, nodes, &config) {
+				Some(spawner) => self.spawners.push(spawner),
+				None => eprintln!("Spawner path {} has no template to spawn", path.spawner_id),
+			}
+		}
+		println!("Loaded {} spawners", self.spawners.len());
+		// no clients yet, so nothing to construct
+		for i in 0..self.spawners.len() {
+			for _ in 0..self.spawners[i].update(0.0) {
+				if let Err(e) = self.spawn_from_spawner(i) {
+					eprintln!("{}", e);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	pub fn is_moderator(&self, username: &str) -> bool {
+		self.moderators.iter().any(|x| x == username)
+	}
+
+	pub fn load_predef_names(&mut self, names_path: &str) -> Res<()> {
+		self.predef_names = PredefNames::load(names_path)?;
+		Ok(())
+	}
+
+	pub fn predef_names(&self) -> &PredefNames {
+		&self.predef_names
+	}
+
+	pub fn zone_id(&self) -> u16 {
+		self.zone_id
+	}
+
+	pub fn zone_checksum(&self) -> u32 {
+		self.zone_checksum
+	}
+
+	pub fn spawn_position(&self) -> Vector3 {
+		self.spawn_position
+	}
+
+	/// Updates spawners and game objects, called by `on_msg` once `TICK_INTERVAL` has passed.
+	///
+	/// The server's run loop only calls back on incoming messages, so ticks happen as often as clients send something, but at most every `TICK_INTERVAL`. Clients ping regularly, and `dt` is the actual time since the last tick, so timers stay accurate. `conn` is the client whose message triggered the tick, so tick code has to address clients through the outbox or broadcast.
+	fn tick(&mut self, conn: &mut Connection) {
+		let dt = self.last_tick.elapsed().as_secs_f32();
+		self.last_tick = Instant::now();
+
+		for i in 0..self.spawners.len() {
+			for _ in 0..self.spawners[i].update(dt) {
+				let res = self.spawn_from_spawner(i).and_then(|obj_id| self.construct(obj_id, conn));
+				if let Err(e) = res {
+					eprintln!("{}", e);
+				}
+			}
+		}
+		let obj_ids: Vec<ObjId> = self.game_objects.keys().copied().collect();
+		for obj_id in obj_ids {
+			// might have been destroyed by an earlier update
+			if !self.game_objects.contains_key(&obj_id) {
+				continue;
+			}
+			if let Err(e) = self.with_game_object(obj_id, |state, game_object| game_object.update(dt, state, conn)) {
+				eprintln!("Error updating game object {}: {}", obj_id, e);
+			}
+		}
+		if let Err(e) = self.flush_destroy_queue(conn) {
+			eprintln!("Error destroying objects: {}", e);
 		}
 	}
 
 	/// Dispatches to the various handlers depending on message type.
 	pub fn on_msg(&mut self, msg: &IncMessage, conn: &mut Connection) {
-		use lu_packets::raknet::server::Message::{InternalPing, ConnectionRequest, NewIncomingConnection, UserMessage};
+		use lu_packets::raknet::server::Message::{InternalPing, ConnectionRequest, NewIncomingConnection, DisconnectionNotification, UserMessage};
 		use lu_packets::world::server::{
 			LuMessage::{General, World},
 			GeneralMessage::Handshake,
 			WorldMessage::ClientValidation,
 		};
+		if let Err(e) = self.outbox.flush(conn) {
+			eprintln!("Error sending queued messages: {}", e);
+		}
+		if self.last_tick.elapsed() >= TICK_INTERVAL {
+			self.tick(conn);
+		}
 		match msg {
 			InternalPing(msg)                         => on_internal_ping::<IncMessage, OutMessage>(msg, conn),
 			ConnectionRequest(msg)                    => on_conn_req::<IncMessage, OutMessage>(msg, conn),
 			NewIncomingConnection(msg)                => { dbg!(msg); Ok(()) },
+			DisconnectionNotification { .. }          => self.on_disconnect(conn),
 			UserMessage(General(Handshake(msg)))      => on_handshake::<IncMessage, OutMessage>(msg, conn, ServiceId::World),
 			UserMessage(World(ClientValidation(msg))) => self.on_client_val(msg, conn),
 			UserMessage(World(msg))                   => self.on_restricted_msg(msg, conn),
 			_                                         => { dbg!("do NOT contact me with unsolicited offers or services"); Ok(()) },
 		}.unwrap();
+		if let Err(e) = self.flush_destroy_queue(conn) {
+			eprintln!("Error destroying objects: {}", e);
+		}
 	}
 
 	fn on_client_val(&mut self, cli_val: &ClientValidation, conn: &mut Connection) -> Res<()> {
@@ -103,7 +369,32 @@ impl State {
 			return Ok(());
 		}
 		let peer_addr = conn.peer_addr().unwrap();
-		self.validated.insert(peer_addr, AccountInfo { username, active_character_id: 0 });
+		self.validated.insert(peer_addr, AccountInfo { username, selected_char_id: None, active_character_id: 0, last_position_save: Instant::now() });
+		Ok(())
+	}
+
+	fn on_disconnect(&mut self, conn: &mut Connection) -> Res<()> {
+		let addr = conn.peer_addr().unwrap();
+		self.replicas.remove(&addr);
+		self.outbox.0.remove(&addr);
+		let mut acc_info = match self.validated.remove(&addr) {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+		if let Err(e) = self.save_char_position(&mut acc_info) {
+			eprintln!("{}", e);
+		}
+		if acc_info.active_character_id != 0 {
+			if let Some(char_id) = acc_info.selected_char_id {
+				if let Err(e) = self.set_char_online(char_id, false) {
+					eprintln!("{}", e);
+				}
+			}
+			self.player_addrs.remove(&acc_info.active_character_id);
+			self.loot.retain(|_, (owner, _)| *owner != acc_info.active_character_id);
+			self.destroy(acc_info.active_character_id, conn)?;
+		}
+		println!("{} disconnected", acc_info.username);
 		Ok(())
 	}
 
@@ -118,19 +409,72 @@ impl State {
 			}
 			Some(info) => info,
 		};
-		on_validated_msg(self, msg, &mut acc_info, conn)?;
+		let res = on_validated_msg(self, msg, &mut acc_info, conn);
 		self.validated.insert(addr, acc_info);
-		Ok(())
+		res
 	}
 
 	pub fn db(&self) -> &SqliteConnection {
 		&self.db
 	}
 
-	pub fn all_game_objects(&self) -> hash_map::Values<ObjId, GameObject> {
-		self.game_objects.values()
+	pub fn cdclient(&self) -> &RusqliteConnection {
+		&self.cdclient
+	}
+
+	pub fn inventory_conf(&self) -> &InventoryConf {
+		&self.inventory_conf
+	}
+
+	pub fn character_conf(&self) -> &CharacterConf {
+		&self.character_conf
+	}
+
+	/// Whether a client connected to this instance has selected the character.
+	pub fn is_char_online(&self, char_id: i32) -> bool {
+		self.validated.values().any(|x| x.selected_char_id() == Some(char_id))
+	}
+
+	/// Records in the users DB whether the character is in a world, so other instances know about it.
+	pub fn set_char_online(&self, char_id: i32, online: bool) -> Res<()> {
+		use crate::schema::characters::dsl::{characters, is_online};
+
+		if let Err(e) = update(characters.find(char_id)).set(is_online.eq(online)).execute(&self.db) {
+			return Err(Error::new(Other, format!("Error saving online status of character {}: {}", char_id, e)));
+		}
+		Ok(())
+	}
+
+	/// Marks the characters in this zone as offline, in case the server was stopped without the clients disconnecting.
+	pub fn reset_online_chars(&self) -> Res<()> {
+		use crate::schema::characters::dsl::{characters, is_online, world_zone};
+
+		if let Err(e) = update(characters.filter(world_zone.eq(self.zone_id as i32)))
+		.set(is_online.eq(false))
+		.execute(&self.db) {
+			return Err(Error::new(Other, format!("Error resetting online status: {}", e)));
+		}
+		Ok(())
+	}
+
+	/// Looks up the world server serving a zone, `None` if it is unknown or served by this instance.
+	pub fn world_for_zone(&self, zone_id: u16) -> Option<&WorldConf> {
+		if zone_id == self.zone_id {
+			return None;
+		}
+		self.worlds.iter().find(|x| x.zone_id == zone_id)
 	}
 
+	/// Whether the object is a player's character.
+	pub fn is_player(&self, obj_id: ObjId) -> bool {
+		self.player_addrs.contains_key(&obj_id)
+	}
+
+	pub fn game_object(&self, obj_id: ObjId) -> Option<&GameObject> {
+		self.game_objects.get(&obj_id)
+	}
+
+
 	pub fn with_game_object<F: FnOnce(&mut State, &mut GameObject) -> Res<()>>(&mut self, obj_id: ObjId, callback: F) -> Res<()> {
 		let mut game_object = match self.game_objects.remove(&obj_id) {
 			Some(x) => x,
@@ -139,9 +483,10 @@ impl State {
 				return Ok(());
 			}
 		};
-		callback(self, &mut game_object)?;
+		let res = callback(self, &mut game_object);
+		// put it back even on errors, or it would be gone from the world without the clients knowing
 		self.game_objects.insert(obj_id, game_object);
-		Ok(())
+		res
 	}
 
 	pub fn with_char<F: FnOnce(&mut State, &mut GameObject) -> Res<()>>(&mut self, acc_info: &AccountInfo, callback: F) -> Res<()> {
@@ -153,30 +498,245 @@ impl State {
 		return self.current_spawned_id;
 	}
 
-	pub fn new_persistent_id(&mut self) -> ObjId {
-		self.current_persistent_id += 1;
-		return self.current_persistent_id;
+	/// Allocates an ID for a persistent object (character, item, property) from the users DB, so IDs stay unique across restarts.
+	///
+	/// The ID is returned without the persistent flag, which only gets applied when talking to the client.
+	pub fn new_persistent_id(&self) -> Res<i64> {
+		use crate::schema::counters::dsl::{counters, value};
+
+		let res = self.db.transaction::<_, diesel::result::Error, _>(|| {
+			update(counters.find("object_id"))
+			.set(value.eq(value + 1))
+			.execute(&self.db)?;
+			counters.find("object_id").select(value).first(&self.db)
+		});
+		match res {
+			Ok(x) => Ok(x),
+			Err(e) => Err(Error::new(Other, format!("Error allocating object ID: {}", e))),
+		}
 	}
 
 	fn new_network_id(&mut self) -> u16 {
+		if let Some(network_id) = self.free_network_ids.pop() {
+			return network_id;
+		}
 		self.current_network_id += 1;
 		return self.current_network_id;
 	}
 
-	pub fn spawn_player(&mut self, acc_info: &mut AccountInfo) -> Res<&mut GameObject> {
-		let chara = self.spawn_internal(true, 1, &lnv!{})?;
+	/// Saves the active character's position and rotation, as long as the character is still in this zone.
+	pub fn save_char_position(&self, acc_info: &mut AccountInfo) -> Res<()> {
+		use crate::schema::characters::dsl::{characters, world_zone, pos_x, pos_y, pos_z, rot_x, rot_y, rot_z, rot_w};
+
+		let char_id = match acc_info.selected_char_id {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+		let chara = match self.game_objects.get(&acc_info.active_character_id) {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+		let mut get_pos = GetPosition::default();
+		chara.run_service(&mut get_pos);
+		let mut get_rot = GetRotation::default();
+		chara.run_service(&mut get_rot);
+
+		if let Err(e) = update(characters
+		.find(char_id)
+		.filter(world_zone.eq(self.zone_id as i32)))
+		.set((
+			pos_x.eq(get_pos.0.x), pos_y.eq(get_pos.0.y), pos_z.eq(get_pos.0.z),
+			rot_x.eq(get_rot.0.x), rot_y.eq(get_rot.0.y), rot_z.eq(get_rot.0.z), rot_w.eq(get_rot.0.w),
+		))
+		.execute(&self.db) {
+			return Err(Error::new(Other, format!("Error saving character position: {}", e)));
+		}
+		acc_info.last_position_save = Instant::now();
+		Ok(())
+	}
+
+	/// Spawns the character selected at login, with the object ID the client knows it by.
+	pub fn spawn_player(&mut self, addr: SocketAddr, acc_info: &mut AccountInfo) -> Res<&mut GameObject> {
+		use crate::schema::characters::dsl::characters;
+
+		let char_id = match acc_info.selected_char_id {
+			Some(x) => x,
+			None => return Err(Error::new(NotFound, format!("{} has not selected a character", acc_info.username))),
+		};
+		let chara: Character = match characters.find(char_id).first(&self.db).optional() {
+			Ok(Some(x)) => x,
+			Ok(None) => return Err(Error::new(NotFound, format!("Character {} does not exist", char_id))),
+			Err(e) => return Err(Error::new(Other, format!("Error loading character {}: {}", char_id, e))),
+		};
+
+		let (position, rotation) = match chara {
+			Character { pos_x: Some(x), pos_y: Some(y), pos_z: Some(z), rot_x: Some(rx), rot_y: Some(ry), rot_z: Some(rz), rot_w: Some(rw), .. } => {
+				(Vector3 { x, y, z }, Quaternion { x: rx, y: ry, z: rz, w: rw })
+			}
+			_ => (self.spawn_position, self.spawn_rotation),
+		};
+
+		let config = lnv! {
+			"char_id": char_id,
+			"name": &chara.name[..],
+			"position_x": position.x,
+			"position_y": position.y,
+			"position_z": position.z,
+			"rotation_x": rotation.x,
+			"rotation_y": rotation.y,
+			"rotation_z": rotation.z,
+			"rotation_w": rotation.w,
+		};
+		let obj_id = (char_id as ObjId) | BITS_PERSISTENT;
+		self.set_char_online(char_id, true)?;
+		self.player_addrs.insert(obj_id, addr);
+		let chara = self.spawn_internal(obj_id, 1, &config)?;
 		acc_info.active_character_id = chara.object_id();
 		Ok(chara)
 	}
 
 	pub fn spawn(&mut self, lot: Lot, config: &LuNameValue) -> Res<&mut GameObject> {
-		self.spawn_internal(false, lot, config)
+		let obj_id = self.new_spawned_id();
+		self.spawn_internal(obj_id, lot, config)
 	}
 
-	fn spawn_internal(&mut self, is_persistent: bool, lot: Lot, config: &LuNameValue) -> Res<&mut GameObject> {
+	/// Removes an object from the world and tells the clients to destroy their replica of it.
+	pub fn destroy(&mut self, obj_id: ObjId, conn: &mut Connection) -> Res<()> {
+		let game_object = match self.game_objects.remove(&obj_id) {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+		let network_id = game_object.network_id();
+		for (addr, client) in &mut self.replicas {
+			if client.known.remove(&network_id) {
+				self.outbox.send(*addr, game_object.make_destruction(), conn)?;
+			}
+		}
+		self.free_network_ids.push(network_id);
+		for spawner in &mut self.spawners {
+			if spawner.on_child_destroyed(obj_id) {
+				break;
+			}
+		}
+		Ok(())
+	}
+
+	/// Tells the owner's client to drop an item from the source object, only the owner can pick it up.
+	pub fn drop_loot(&mut self, lot: Lot, owner: ObjId, source: ObjId, position: Vector3, conn: &mut Connection) -> Res<()> {
+		// only players get loot
+		let addr = match self.player_addrs.get(&owner) {
+			Some(x) => *x,
+			None => return Ok(()),
+		};
+		let loot_id = self.new_spawned_id();
+		self.loot.insert(loot_id, (owner, lot));
+		self.outbox.send(addr, ClientSGM {
+			subject_id: owner,
+			message: DropClientLoot {
+				use_position: false,
+				final_position: Vector3::ZERO,
+				// characters don't have coins yet, so only items drop
+				currency: 0,
+				item_template: lot,
+				loot_id,
+				owner,
+				source_obj: source,
+				spawn_position: position,
+			}.into(),
+		}, conn)
+	}
+
+	/// Removes loot dropped for the owner and returns its LOT, `None` if the owner has no such loot.
+	pub fn take_loot(&mut self, loot_id: ObjId, owner: ObjId) -> Option<Lot> {
+		match self.loot.get(&loot_id) {
+			Some((x, _)) if *x == owner => self.loot.remove(&loot_id).map(|(_, lot)| lot),
+			_ => None,
+		}
+	}
+
+	/// Destroys the object after the current message or tick has been handled.
+	///
+	/// Objects being handled are not in the world until their handler returns, this way they can destroy themselves.
+	pub fn queue_destroy(&mut self, obj_id: ObjId) {
+		self.destroy_queue.push(obj_id);
+	}
+
+	fn flush_destroy_queue(&mut self, conn: &mut Connection) -> Res<()> {
+		for obj_id in std::mem::take(&mut self.destroy_queue) {
+			self.destroy(obj_id, conn)?;
+		}
+		Ok(())
+	}
+
+	/// Moves the client's point of view, constructing the objects that are now in range and destroying those that are not.
+	///
+	/// Also starts sending replica updates to the client if it's new to the world.
+	pub fn update_ghosts_for(&mut self, addr: SocketAddr, position: Vector3, conn: &mut Connection) -> Res<()> {
+		let client = self.replicas.entry(addr).or_insert_with(|| ReplicaClient { position, known: HashSet::new() });
+		client.position = position;
+		for game_object in self.game_objects.values() {
+			client.update_ghost(addr, game_object, &self.ghosting_conf, &mut self.outbox, conn)?;
+		}
+		Ok(())
+	}
+
+	/// Constructs or destroys the object's replica for every client in the world, depending on whether they are in range of it.
+	pub fn update_ghost(&mut self, game_object: &GameObject, conn: &mut Connection) -> Res<()> {
+		for (addr, client) in &mut self.replicas {
+			client.update_ghost(*addr, game_object, &self.ghosting_conf, &mut self.outbox, conn)?;
+		}
+		Ok(())
+	}
+
+	/// Constructs a newly spawned object for the clients in range of it.
+	pub fn construct(&mut self, obj_id: ObjId, conn: &mut Connection) -> Res<()> {
+		let game_object = match self.game_objects.get(&obj_id) {
+			Some(x) => x,
+			None => return Err(Error::new(NotFound, format!("Game object {} does not exist", obj_id))),
+		};
+		for (addr, client) in &mut self.replicas {
+			client.update_ghost(*addr, game_object, &self.ghosting_conf, &mut self.outbox, conn)?;
+		}
+		Ok(())
+	}
+
+	/// Sends the object's changed state to the clients that have constructed it, if anything changed.
+	pub fn serialize(&mut self, game_object: &mut GameObject, exclude: Option<SocketAddr>, conn: &mut Connection) -> Res<()> {
+		if !game_object.is_dirty() {
+			return Ok(());
+		}
+		for (addr, client) in &self.replicas {
+			if Some(*addr) != exclude && client.known.contains(&game_object.network_id()) {
+				self.outbox.send(*addr, game_object.make_serialization(), conn)?;
+			}
+		}
+		game_object.clear_dirty();
+		Ok(())
+	}
+
+	/// Spawns the spawner's object at one of its free nodes.
+	fn spawn_from_spawner(&mut self, index: usize) -> Res<ObjId> {
+		let spawner = &mut self.spawners[index];
+		let (spawner_id, lot) = (spawner.id(), spawner.lot());
+		let (node_index, config) = match spawner.next_spawn() {
+			Some(x) => x,
+			None => return Err(Error::new(Other, format!("Spawner {} has no free node", spawner_id))),
+		};
+		let obj_id = match self.spawn(lot, &config) {
+			Ok(x) => x.object_id(),
+			Err(e) => return Err(Error::new(Other, format!("Spawner {} could not spawn LOT {}: {}", spawner_id, lot, e))),
+		};
+		self.spawners[index].add_child(obj_id, node_index);
+		Ok(obj_id)
+	}
+
+	fn spawn_internal(&mut self, obj_id: ObjId, lot: Lot, config: &LuNameValue) -> Res<&mut GameObject> {
+		if self.game_objects.contains_key(&obj_id) {
+			return Err(Error::new(Other, format!("Game object {} already exists", obj_id)));
+		}
 		let network_id = self.new_network_id();
-		let obj_id = if is_persistent { self.new_persistent_id() } else { self.new_spawned_id() };
-		let game_object = GameObject::new(network_id, obj_id, lot, config, &self.cdclient)?;
+		let mut game_object = GameObject::new(network_id, obj_id, lot, config, &self.cdclient)?;
+		game_object.load(self)?;
 		self.game_objects.insert(obj_id, game_object);
 		Ok(self.game_objects.get_mut(&obj_id).unwrap())
 	}
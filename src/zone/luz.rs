@@ -0,0 +1,398 @@
+use std::fs::File;
+use std::io::{BufReader, Cursor, Error, ErrorKind::{InvalidData, NotFound}, Read, Result as Res};
+use std::path::Path;
+
+use rusqlite::{Connection as RusqliteConnection, params};
+
+use lu_packets::world::{Lot, Quaternion, Vector3};
+
+use super::ReadExt;
+use super::lvl::{self, SceneObject};
+
+/// A scene of a zone, with the objects from its level file.
+pub struct Scene {
+	pub id: u32,
+	/// Revision of the level file.
+	pub revision: u32,
+	pub objects: Vec<SceneObject>,
+}
+
+/// A spawner defined by a path of the zone, with a node at each waypoint.
+pub struct SpawnerPath {
+	/// Object ID of the spawner.
+	pub spawner_id: u64,
+	pub lot: Lot,
+	/// Seconds until a destroyed object is respawned.
+	pub respawn_time: u32,
+	pub number_to_maintain: u32,
+	pub active_on_load: bool,
+	pub nodes: Vec<(Vector3, Quaternion)>,
+}
+
+/// Checksums verified against the client, by zone ID.
+const KNOWN_CHECKSUMS: &[(u16, u32)] = &[
+	(1100, 0x49525511),
+];
+
+/// Looks up the checksum the client is known to expect for a zone.
+pub fn known_checksum(zone_id: u16) -> Option<u32> {
+	KNOWN_CHECKSUMS.iter().find(|(x, _)| *x == zone_id).map(|(_, checksum)| *checksum)
+}
+
+/// Upper bound for the counts in the file, anything larger means the file is being read wrong.
+const MAX_COUNT: u32 = 0x10000;
+
+const PATH_MOVEMENT: u32 = 0;
+const PATH_MOVING_PLATFORM: u32 = 1;
+const PATH_PROPERTY: u32 = 2;
+const PATH_CAMERA: u32 = 3;
+const PATH_SPAWNER: u32 = 4;
+const PATH_RACE: u32 = 6;
+const PATH_RAIL: u32 = 7;
+
+/// A zone as described by its .luz file.
+pub struct Zone {
+	/// Revision of the zone file.
+	pub revision: u32,
+	pub spawn_position: Vector3,
+	pub spawn_rotation: Quaternion,
+	pub scenes: Vec<Scene>,
+	pub spawner_paths: Vec<SpawnerPath>,
+}
+
+impl Zone {
+	/// Loads the zone's .luz file referenced by the cdclient `ZoneTable`, and the .lvl files of its scenes.
+	pub fn load(maps_path: &str, zone_id: u16, cdclient: &RusqliteConnection) -> Res<Self> {
+		let mut stmt = cdclient.prepare("select zoneName from zonetable where zoneID = ?").unwrap();
+		let zone_name: String = match stmt.query_row(params![zone_id], |row| row.get(0)) {
+			Ok(x) => x,
+			Err(_) => return Err(Error::new(NotFound, format!("zone {} not in ZoneTable", zone_id))),
+		};
+		let luz_path = Path::new(maps_path).join(zone_name.replace('\\', "/"));
+		let mut reader = BufReader::new(File::open(&luz_path)?);
+
+		let version = reader.read_u32()?;
+		let revision = if version >= 0x24 { reader.read_u32()? } else { 0 };
+		let world_id = reader.read_u32()?;
+		if world_id != zone_id as u32 {
+			println!("World ID {} in {} doesn't match zone ID {}", world_id, luz_path.display(), zone_id);
+		}
+		let (spawn_position, spawn_rotation) = if version >= 0x26 {
+			(reader.read_vector3()?, reader.read_quaternion()?)
+		} else {
+			(Vector3::ZERO, Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 })
+		};
+		let scene_count = if version >= 0x26 { reader.read_u32()? } else { reader.read_u8()? as u32 };
+
+		let luz_dir = luz_path.parent().unwrap();
+		let mut scenes = vec![];
+		for _ in 0..scene_count {
+			let filename = reader.read_str_u8()?;
+			let id = reader.read_u32()?;
+			let _scene_type = reader.read_u32()?;
+			let _name = reader.read_str_u8()?;
+			if version == 0x21 {
+				let _ = reader.read_f32()?;
+				let _ = reader.read_f32()?;
+			}
+			if version >= 0x21 {
+				let _color = (reader.read_u8()?, reader.read_u8()?, reader.read_u8()?);
+			}
+			let lvl_path = luz_dir.join(filename.replace('\\', "/"));
+			let (mut revision, objects) = match lvl::load(&lvl_path) {
+				Ok(x) => x,
+				Err(e) => return Err(Error::new(InvalidData, format!("error loading {}: {}", lvl_path.display(), e))),
+			};
+			// special case: some Avant Gardens level files have an uninitialized revision, the client expects 26
+			if revision == 0xcdcdcdcd && zone_id == 1100 {
+				revision = 26;
+			}
+			scenes.push(Scene { id, revision, objects });
+		}
+
+		let mut spawner_paths = vec![];
+		if version >= 0x23 {
+			let mut rest = vec![];
+			reader.read_to_end(&mut rest)?;
+			match Self::read_spawner_paths(version, &rest) {
+				Ok(x) => spawner_paths = x,
+				Err(e) => eprintln!("Error reading paths of {}, not using any spawner paths: {}", luz_path.display(), e),
+			}
+		}
+
+		Ok(Self {
+			revision,
+			spawn_position,
+			spawn_rotation,
+			scenes,
+			spawner_paths,
+		})
+	}
+
+	/// Reads the spawner paths from the part of the file after the scenes, skipping the scene transitions and other paths.
+	///
+	/// Only returns the spawner paths if all paths could be read, since a misread file would mean spawners with wrong objects in wrong places.
+	fn read_spawner_paths(version: u32, rest: &[u8]) -> Res<Vec<SpawnerPath>> {
+		let mut reader = Cursor::new(rest);
+		// terrain file, raw path, name and description of the zone
+		for _ in 0..4 {
+			reader.read_str_u8()?;
+		}
+		if version >= 0x20 {
+			let transition_count = reader.read_u32()?;
+			if transition_count > MAX_COUNT {
+				return Err(Error::new(InvalidData, format!("implausible scene transition count {}", transition_count)));
+			}
+			for _ in 0..transition_count {
+				if version < 0x25 {
+					let _name = reader.read_str_u8()?;
+				}
+				let point_count = if version <= 0x21 || version >= 0x27 { 2 } else { 5 };
+				for _ in 0..point_count {
+					let _scene_id = reader.read_u64()?;
+					let _position = reader.read_vector3()?;
+				}
+			}
+		}
+
+		// the path chunk's length has to match the rest of the file, otherwise the transitions were read wrong
+		let chunk_offset = reader.position() as usize;
+		let chunk_len = reader.read_u32()? as usize;
+		if chunk_offset + 4 + chunk_len != rest.len() {
+			return Err(Error::new(InvalidData, format!("path chunk at offset {} after the scenes has length {}, but {} bytes are left", chunk_offset, chunk_len, rest.len() - chunk_offset - 4)));
+		}
+		let _chunk_version = reader.read_u32()?;
+		let path_count = reader.read_u32()?;
+		if path_count > MAX_COUNT {
+			return Err(Error::new(InvalidData, format!("implausible path count {}", path_count)));
+		}
+		let mut spawner_paths = vec![];
+		for _ in 0..path_count {
+			let path_version = reader.read_u32()?;
+			let name = reader.read_wstr_u8()?;
+			let path_type = reader.read_u32()?;
+			if path_type > PATH_RAIL {
+				return Err(Error::new(InvalidData, format!("unknown type {} of path {}", path_type, name)));
+			}
+			let _flags = reader.read_u32()?;
+			let _behavior = reader.read_u32()?;
+
+			let mut spawner = None;
+			match path_type {
+				PATH_MOVING_PLATFORM => {
+					if path_version >= 18 {
+						let _time_based = reader.read_u8()?;
+					} else if path_version >= 13 {
+						let _travel_sound = reader.read_wstr_u8()?;
+					}
+				}
+				PATH_PROPERTY => {
+					let _property_path_type = reader.read_u32()?;
+					let _price = reader.read_u32()?;
+					let _rental_time = reader.read_u32()?;
+					let _associated_zone = reader.read_u64()?;
+					if path_version >= 5 {
+						let _display_name = reader.read_wstr_u8()?;
+						let _display_desc = reader.read_wstr_u32()?;
+					}
+					if path_version >= 6 {
+						let _type = reader.read_u32()?;
+					}
+					if path_version >= 7 {
+						let _clone_limit = reader.read_u32()?;
+						let _rep_multiplier = reader.read_f32()?;
+						let _rental_time_unit = reader.read_u32()?;
+					}
+					if path_version >= 8 {
+						let _achievement_required = reader.read_u32()?;
+					}
+					if path_version >= 9 {
+						let _player_zone_coords = reader.read_vector3()?;
+						let _max_build_height = reader.read_f32()?;
+					}
+				}
+				PATH_CAMERA => {
+					let _next_path = reader.read_wstr_u8()?;
+					if path_version >= 14 {
+						let _rotate_player = reader.read_u8()?;
+					}
+				}
+				PATH_SPAWNER => {
+					let lot = reader.read_u32()? as Lot;
+					if lot <= 0 {
+						return Err(Error::new(InvalidData, format!("implausible LOT {} of spawner path {}", lot, name)));
+					}
+					let respawn_time = reader.read_u32()?;
+					let _max_to_spawn = reader.read_u32()?;
+					let number_to_maintain = reader.read_u32()?;
+					let spawner_id = reader.read_u64()?;
+					let active_on_load = reader.read_u8()? != 0;
+					spawner = Some(SpawnerPath { spawner_id, lot, respawn_time, number_to_maintain, active_on_load, nodes: vec![] });
+				}
+				_ => {}
+			}
+
+			let waypoint_count = reader.read_u32()?;
+			if waypoint_count > MAX_COUNT {
+				return Err(Error::new(InvalidData, format!("implausible waypoint count {} of path {}", waypoint_count, name)));
+			}
+			for _ in 0..waypoint_count {
+				let position = reader.read_vector3()?;
+				let rotation = match path_type {
+					PATH_MOVING_PLATFORM | PATH_SPAWNER | PATH_RACE | PATH_RAIL => reader.read_quaternion()?,
+					_ => Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+				};
+				match path_type {
+					PATH_MOVING_PLATFORM => {
+						let _lock_player = reader.read_u8()?;
+						let _speed = reader.read_f32()?;
+						let _wait = reader.read_f32()?;
+						if path_version >= 13 {
+							let _depart_sound = reader.read_wstr_u8()?;
+							let _arrive_sound = reader.read_wstr_u8()?;
+						}
+					}
+					PATH_CAMERA => {
+						// time, field of view, tension, continuity, bias
+						for _ in 0..5 {
+							reader.read_f32()?;
+						}
+					}
+					PATH_RACE => {
+						let _is_reset_node = reader.read_u8()?;
+						let _is_non_horizontal_camera = reader.read_u8()?;
+						let _plane_width = reader.read_f32()?;
+						let _plane_height = reader.read_f32()?;
+						let _shortest_distance_to_end = reader.read_f32()?;
+					}
+					PATH_RAIL => {
+						if path_version > 16 {
+							let _speed = reader.read_f32()?;
+						}
+					}
+					_ => {}
+				}
+				if let PATH_MOVEMENT | PATH_SPAWNER | PATH_RAIL = path_type {
+					let config_count = reader.read_u32()?;
+					for _ in 0..config_count {
+						let _key = reader.read_wstr_u8()?;
+						let _value = reader.read_wstr_u8()?;
+					}
+				}
+				if let Some(spawner) = &mut spawner {
+					spawner.nodes.push((position, rotation));
+				}
+			}
+			if let Some(spawner) = spawner {
+				spawner_paths.push(spawner);
+			}
+		}
+		if reader.position() as usize != rest.len() {
+			return Err(Error::new(InvalidData, format!("{} bytes left after the paths", rest.len() - reader.position() as usize)));
+		}
+		Ok(spawner_paths)
+	}
+
+	/// Computes the checksum the client uses to verify that its zone files match ours.
+	///
+	/// This is a Fletcher-style checksum over the scene IDs, layer IDs and revisions, starting with the zone's own revision.
+	pub fn checksum(&self) -> u32 {
+		// (scene ID, layer ID, revision), the zone itself has scene ID -1
+		let mut revisions = vec![(-1i32, 0u32, self.revision)];
+		for scene in &self.scenes {
+			revisions.push((scene.id as i32, 0, scene.revision));
+		}
+		revisions.sort_by_key(|x| (x.0, x.1));
+
+		let mut sum1: u32 = 0xffff;
+		let mut sum2: u32 = 0xffff;
+		for (scene_id, layer_id, revision) in revisions {
+			for value in &[scene_id as u32, layer_id, revision] {
+				sum1 = sum1.wrapping_add(value >> 16);
+				sum2 = sum2.wrapping_add(sum1);
+				sum1 = sum1.wrapping_add(value & 0xffff);
+				sum2 = sum2.wrapping_add(sum1);
+			}
+		}
+		sum1 = (sum1 & 0xffff) + (sum1 >> 16);
+		sum2 = (sum2 & 0xffff) + (sum2 >> 16);
+		sum2 << 16 | sum1
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use lu_packets::world::{Quaternion, Vector3};
+
+	use super::{Scene, Zone};
+
+	fn zone(revision: u32, scenes: &[(u32, u32)]) -> Zone {
+		Zone {
+			revision,
+			spawn_position: Vector3::ZERO,
+			spawn_rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+			scenes: scenes.iter().map(|&(id, revision)| Scene { id, revision, objects: vec![] }).collect(),
+			spawner_paths: vec![],
+		}
+	}
+
+	#[test]
+	fn checksum() {
+		assert_eq!(zone(5, &[(0, 26), (1, 3)]).checksum(), 0x00ff0023);
+		// scene order in the file doesn't matter
+		assert_eq!(zone(5, &[(1, 3), (0, 26)]).checksum(), 0x00ff0023);
+	}
+
+	/// The part of a version 0x26 file after the scenes, with no transitions and a single spawner path with one waypoint.
+	fn path_data(chunk_len_offset: i32) -> Vec<u8> {
+		let mut chunk = vec![];
+		chunk.extend(&18u32.to_le_bytes()); // chunk version
+		chunk.extend(&1u32.to_le_bytes()); // path count
+		chunk.extend(&18u32.to_le_bytes()); // path version
+		chunk.extend(&[1, b'a', 0]); // name
+		chunk.extend(&4u32.to_le_bytes()); // type
+		chunk.extend(&[0; 8]); // flags, behavior
+		chunk.extend(&1234u32.to_le_bytes()); // LOT
+		chunk.extend(&10u32.to_le_bytes()); // respawn time
+		chunk.extend(&u32::MAX.to_le_bytes()); // max to spawn
+		chunk.extend(&1u32.to_le_bytes()); // number to maintain
+		chunk.extend(&42u64.to_le_bytes()); // spawner ID
+		chunk.push(1); // active on load
+		chunk.extend(&1u32.to_le_bytes()); // waypoint count
+		for x in &[1.0f32, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0] {
+			chunk.extend(&x.to_le_bytes());
+		}
+		chunk.extend(&0u32.to_le_bytes()); // config count
+
+		let mut data = vec![0; 4]; // empty zone strings
+		data.extend(&0u32.to_le_bytes()); // transition count
+		data.extend(&((chunk.len() as i32 + chunk_len_offset) as u32).to_le_bytes());
+		data.extend(chunk);
+		data
+	}
+
+	#[test]
+	fn spawner_paths() {
+		let paths = Zone::read_spawner_paths(0x26, &path_data(0)).unwrap();
+		assert_eq!(paths.len(), 1);
+		assert_eq!(paths[0].lot, 1234);
+		assert_eq!(paths[0].spawner_id, 42);
+		assert_eq!(paths[0].number_to_maintain, 1);
+		assert!(paths[0].active_on_load);
+		assert_eq!(paths[0].nodes.len(), 1);
+		assert_eq!((paths[0].nodes[0].0.x, paths[0].nodes[0].0.y, paths[0].nodes[0].0.z), (1.0, 2.0, 3.0));
+		// a chunk length that doesn't match means the file was misread
+		assert!(Zone::read_spawner_paths(0x26, &path_data(1)).is_err());
+	}
+
+	/// Needs the client's files, run with `LU_MAPS_PATH` and `LU_CDCLIENT_PATH` set and `--ignored`.
+	#[test]
+	#[ignore]
+	fn checksum_avant_gardens() {
+		let maps_path = std::env::var("LU_MAPS_PATH").expect("LU_MAPS_PATH not set");
+		let cdclient_path = std::env::var("LU_CDCLIENT_PATH").expect("LU_CDCLIENT_PATH not set");
+		let cdclient = rusqlite::Connection::open(cdclient_path).unwrap();
+		let zone = Zone::load(&maps_path, 1100, &cdclient).unwrap();
+		assert_eq!(zone.checksum(), 0x49525511);
+	}
+}
@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind::InvalidData, Read, Result as Res, Seek, SeekFrom};
+use std::path::Path;
+
+use lu_packets::world::{Lot, LuNameValue, Quaternion, Vector3};
+
+use super::{parse_ldf, ReadExt};
+
+const CHUNK_MAGIC: &[u8; 4] = b"CHNK";
+const CHUNK_FILE_INFO: u32 = 1000;
+const CHUNK_SCENE_OBJECT_DATA: u32 = 2001;
+
+/// An object placed in a level file.
+pub struct SceneObject {
+	pub id: u64,
+	pub lot: Lot,
+	pub position: Vector3,
+	pub rotation: Quaternion,
+	pub scale: f32,
+	pub config: LuNameValue,
+}
+
+/// Loads a level file, returning its revision and objects.
+pub fn load(path: &Path) -> Res<(u32, Vec<SceneObject>)> {
+	let mut reader = BufReader::new(File::open(path)?);
+	let len = reader.seek(SeekFrom::End(0))?;
+	reader.seek(SeekFrom::Start(0))?;
+
+	let mut version = 0;
+	let mut revision = 0;
+	let mut objects = vec![];
+
+	let mut chunk_start = 0;
+	while chunk_start < len {
+		reader.seek(SeekFrom::Start(chunk_start))?;
+		let mut magic = [0; 4];
+		reader.read_exact(&mut magic)?;
+		if &magic != CHUNK_MAGIC {
+			return Err(Error::new(InvalidData, "old level format without chunks is not supported"));
+		}
+		let chunk_id = reader.read_u32()?;
+		let _header_version = reader.read_u16()?;
+		let _chunk_version = reader.read_u16()?;
+		let chunk_size = reader.read_u32()?;
+		let data_start = reader.read_u32()?;
+		reader.seek(SeekFrom::Start(data_start as u64))?;
+
+		match chunk_id {
+			CHUNK_FILE_INFO => {
+				version = reader.read_u32()?;
+				revision = reader.read_u32()?;
+			}
+			CHUNK_SCENE_OBJECT_DATA => {
+				objects = read_objects(&mut reader, version)?;
+			}
+			_ => {}
+		}
+		if chunk_size == 0 {
+			break;
+		}
+		chunk_start += chunk_size as u64;
+	}
+	Ok((revision, objects))
+}
+
+fn read_objects<R: ReadExt>(reader: &mut R, version: u32) -> Res<Vec<SceneObject>> {
+	let count = reader.read_u32()?;
+	let mut objects = Vec::with_capacity(count as usize);
+	for _ in 0..count {
+		let id = reader.read_u64()?;
+		let lot = reader.read_u32()? as Lot;
+		if version >= 0x26 {
+			let _node_type = reader.read_u32()?;
+		}
+		if version >= 0x20 {
+			let _glom_id = reader.read_u32()?;
+		}
+		let position = reader.read_vector3()?;
+		let rotation = reader.read_quaternion()?;
+		let scale = reader.read_f32()?;
+		let config = parse_ldf(&reader.read_wstr_u32()?);
+		if version >= 7 {
+			let render_tech_count = reader.read_u32()?;
+			if render_tech_count != 0 {
+				return Err(Error::new(InvalidData, "objects with render techniques are not supported"));
+			}
+		}
+		objects.push(SceneObject { id, lot, position, rotation, scale, config });
+	}
+	Ok(objects)
+}
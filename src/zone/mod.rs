@@ -0,0 +1,137 @@
+//! Loading of zone (.luz) and level (.lvl) files.
+mod luz;
+mod lvl;
+
+use std::io::{Error, ErrorKind::InvalidData, Read, Result as Res};
+
+use lu_packets::{
+	lnv, lu,
+	world::{LuNameValue, LnvValue, Quaternion, Vector3},
+};
+
+pub use self::luz::{known_checksum, Scene, SpawnerPath, Zone};
+pub use self::lvl::SceneObject;
+
+/// Little-endian primitive reading for the zone file formats.
+trait ReadExt: Read {
+	fn read_u8(&mut self) -> Res<u8> {
+		let mut buf = [0; 1];
+		self.read_exact(&mut buf)?;
+		Ok(buf[0])
+	}
+
+	fn read_u16(&mut self) -> Res<u16> {
+		let mut buf = [0; 2];
+		self.read_exact(&mut buf)?;
+		Ok(u16::from_le_bytes(buf))
+	}
+
+	fn read_u32(&mut self) -> Res<u32> {
+		let mut buf = [0; 4];
+		self.read_exact(&mut buf)?;
+		Ok(u32::from_le_bytes(buf))
+	}
+
+	fn read_u64(&mut self) -> Res<u64> {
+		let mut buf = [0; 8];
+		self.read_exact(&mut buf)?;
+		Ok(u64::from_le_bytes(buf))
+	}
+
+	fn read_f32(&mut self) -> Res<f32> {
+		let mut buf = [0; 4];
+		self.read_exact(&mut buf)?;
+		Ok(f32::from_le_bytes(buf))
+	}
+
+	fn read_vector3(&mut self) -> Res<Vector3> {
+		Ok(Vector3 { x: self.read_f32()?, y: self.read_f32()?, z: self.read_f32()? })
+	}
+
+	/// Quaternions are stored in w, x, y, z order.
+	fn read_quaternion(&mut self) -> Res<Quaternion> {
+		let w = self.read_f32()?;
+		Ok(Quaternion { x: self.read_f32()?, y: self.read_f32()?, z: self.read_f32()?, w })
+	}
+
+	/// Reads a string with a u8 length prefix.
+	fn read_str_u8(&mut self) -> Res<String> {
+		let len = self.read_u8()?;
+		let mut buf = vec![0; len as usize];
+		self.read_exact(&mut buf)?;
+		String::from_utf8(buf).map_err(|e| Error::new(InvalidData, e))
+	}
+
+	/// Reads a UTF-16 string with a u8 length prefix.
+	fn read_wstr_u8(&mut self) -> Res<String> {
+		let len = self.read_u8()?;
+		let mut buf = Vec::with_capacity(len as usize);
+		for _ in 0..len {
+			buf.push(self.read_u16()?);
+		}
+		String::from_utf16(&buf).map_err(|e| Error::new(InvalidData, e))
+	}
+
+	/// Reads a UTF-16 string with a u32 length prefix.
+	fn read_wstr_u32(&mut self) -> Res<String> {
+		let len = self.read_u32()?;
+		let mut buf = Vec::with_capacity(len as usize);
+		for _ in 0..len {
+			buf.push(self.read_u16()?);
+		}
+		String::from_utf16(&buf).map_err(|e| Error::new(InvalidData, e))
+	}
+}
+
+impl<R: Read> ReadExt for R {}
+
+/// Parses LDF config of the form `key=type:value`, one entry per line.
+fn parse_ldf(string: &str) -> LuNameValue {
+	let mut config = lnv! {};
+	for line in string.lines() {
+		let (key, rest) = match line.find('=') {
+			Some(i) => (&line[..i], &line[i+1..]),
+			None => continue,
+		};
+		let (ty, value) = match rest.find(':') {
+			Some(i) => (&rest[..i], &rest[i+1..]),
+			None => continue,
+		};
+		let value = match ty {
+			"0" | "13" => LnvValue::WString(lu!(value)),
+			"1"        => match value.parse() { Ok(x) => LnvValue::I32(x), Err(_) => continue },
+			"3"        => match value.parse() { Ok(x) => LnvValue::F32(x), Err(_) => continue },
+			"4"        => match value.parse() { Ok(x) => LnvValue::F64(x), Err(_) => continue },
+			"5"        => match value.parse() { Ok(x) => LnvValue::U32(x), Err(_) => continue },
+			"7"        => LnvValue::Bool(value == "1"),
+			"8" | "9"  => match value.parse() { Ok(x) => LnvValue::I64(x), Err(_) => continue },
+			_ => {
+				eprintln!("Unknown LDF type {} for key {}", ty, key);
+				continue;
+			}
+		};
+		config.insert(lu!(key), value);
+	}
+	config
+}
+
+#[cfg(test)]
+mod tests {
+	use lu_packets::{lu, world::LnvValue};
+
+	use super::parse_ldf;
+
+	#[test]
+	fn ldf() {
+		let config = parse_ldf("name=0:Spawner\nspawntemplate=1:4804\nrespawn=3:20.5\nbig=8:1152921504606846976\nactive=7:1\nbroken\nnotype=5\nunknown=99:1\nbadint=1:x");
+		assert!(matches!(config.get(&lu!("name")), Some(LnvValue::WString(x)) if x.to_string() == "Spawner"));
+		assert!(matches!(config.get(&lu!("spawntemplate")), Some(LnvValue::I32(4804))));
+		assert!(matches!(config.get(&lu!("respawn")), Some(LnvValue::F32(x)) if *x == 20.5));
+		assert!(matches!(config.get(&lu!("big")), Some(LnvValue::I64(1152921504606846976))));
+		assert!(matches!(config.get(&lu!("active")), Some(LnvValue::Bool(true))));
+		assert!(config.get(&lu!("broken")).is_none());
+		assert!(config.get(&lu!("notype")).is_none());
+		assert!(config.get(&lu!("unknown")).is_none());
+		assert!(config.get(&lu!("badint")).is_none());
+	}
+}